@@ -1,59 +1,898 @@
-use std::path::Path;
-use std::process::Command;
+use std::env;
+use std::fs;
+use std::io::{self, ErrorKind, Read};
+use std::os::unix::process::ExitStatusExt;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, ExitStatus, Output, Stdio};
+use std::sync::OnceLock;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
 
 use anyhow::{anyhow, Result};
+use regex::Regex;
+use thiserror::Error;
 
-use crate::package::Packages;
+use crate::check_cache::CheckCache;
+use crate::project::{Project, ARTIFACT_DIRS};
 use crate::shared;
 
+const DEFAULT_CONTAINER_IMAGE: &str = "epitechcontent/epitest-docker";
+const CONTAINER_RUNTIMES: [&str; 2] = ["docker", "podman"];
+/// How often a running lambdananas is checked against the limits
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// Exit code when lambdananas was stopped for going over --timeout, like timeout(1)
+pub const TIMEOUT_EXIT_CODE: i32 = 124;
+/// Exit code when lambdananas was stopped for going over --memory-limit, like a SIGKILL
+pub const MEMORY_EXIT_CODE: i32 = 137;
+
+/// How lambdananas should be run
+pub struct RunOptions {
+    pub container: bool,
+    pub container_image: Option<String>,
+    /// lambdananas executable, CS2_LAMBDANANAS or the one in PATH if None
+    pub lambdananas: Option<String>,
+    /// Extra arguments given to lambdananas
+    pub lambdananas_args: Vec<String>,
+    /// Parse what lambdananas printed even if it failed
+    pub keep_going: bool,
+    /// Seconds after which lambdananas is killed
+    pub timeout: Option<u64>,
+    /// Megabytes of memory after which lambdananas is killed
+    pub memory_limit: Option<u64>,
+    /// Number of lambdananas processes checking the project at the same time
+    pub jobs: usize,
+    /// Only check the files that changed since the last run, reusing the cached results
+    pub incremental: bool,
+}
+
+#[derive(Error, Debug)]
+enum RunError {
+    #[error("Couldn't find {0}, make sure you ran cs2-haskell install before")]
+    Missing(String),
+
+    #[error("{0} crashed ({1}){2}")]
+    Crashed(String, String, String),
+
+    #[error("{0} failed ({1}){2}")]
+    Failed(String, String, String),
+
+    #[error("{0} couldn't parse {1}{2}")]
+    Parse(String, String, String),
+
+    #[error("{0} timed out after {1}s while checking {2}")]
+    TimedOut(String, u64, String),
+
+    #[error("{0} used more than {1} MB of memory while checking {2}")]
+    OutOfMemory(String, u64, String),
+}
+
+/// Why lambdananas was killed before it exited by itself
+enum Stopped {
+    TimedOut,
+    OutOfMemory,
+}
+
+const STDERR_MAX_LINES: usize = 20;
+
 enum BuildSystems {
-    Default,
+    Default {
+        lambdananas: String,
+    },
+    /// Runs lambdananas inside a container, with the project mounted read-only
+    Container {
+        runtime: String,
+        image: String,
+    },
 }
 
 impl BuildSystems {
-    fn build(&self) -> Result<Vec<String>> {
-        self.clean()?;
+    /// Runs lambdananas on the project, or the source directories of project if there is one,
+    /// leaving out the generated files
+    fn check_project(
+        &self,
+        options: &RunOptions,
+        project: Option<&Project>,
+        generated: &[String],
+    ) -> Result<Vec<String>> {
+        let mut files = match project {
+            Some(project) => get_project_files(project),
+            None if options.incremental => {
+                let mut files = Vec::new();
+                find_haskell_files(Path::new("."), &mut files);
+                files
+            }
+            None => {
+                let (output, _) = self.check_groups(&split_project(options.jobs)?, options)?;
+                return Ok(shared::split_output(output));
+            }
+        };
+
+        files.retain(|file| {
+            !generated
+                .iter()
+                .any(|generated| generated == shared::skip_leading_dot(file))
+        });
+        if files.is_empty() {
+            return Ok(Vec::new());
+        }
+        if !options.incremental {
+            let (output, _) = self.check_groups(&split_files(files, options.jobs), options)?;
+            return Ok(shared::split_output(output));
+        }
+
+        let cache = CheckCache::open(&self.get_checker_id(options))?;
+        let mut lines = Vec::new();
+        let mut changed = Vec::new();
+
+        for file in files {
+            let key = cache.key(&file)?;
+
+            match cache.get(&key) {
+                Some(mut cached) => lines.append(&mut cached),
+                None => changed.push((file, key)),
+            }
+        }
+
+        if changed.is_empty() {
+            return Ok(lines);
+        }
+
+        let paths = changed
+            .iter()
+            .map(|(file, _)| file.clone())
+            .collect::<Vec<_>>();
+        let (output, succeeded) = self.check_groups(&split_files(paths, options.jobs), options)?;
+        let mut output = shared::split_output(output);
+
+        // What a failing lambdananas printed may be missing errors
+        if succeeded {
+            cache.store(&changed, &output)?;
+        }
+        lines.append(&mut output);
+        Ok(lines)
+    }
+
+    /// Runs a lambdananas process per group of paths at the same time.
+    /// Returns their merged output, and whether they all succeeded.
+    fn check_groups(
+        &self,
+        groups: &[Vec<String>],
+        options: &RunOptions,
+    ) -> Result<(Vec<u8>, bool)> {
+        let outputs = if groups.len() == 1 {
+            vec![self.check(&groups[0], 0, options)]
+        } else {
+            thread::scope(|scope| {
+                let handles = groups
+                    .iter()
+                    .enumerate()
+                    .map(|(job, paths)| scope.spawn(move || self.check(paths, job, options)))
+                    .collect::<Vec<_>>();
+
+                handles
+                    .into_iter()
+                    .map(|handle| {
+                        handle
+                            .join()
+                            .unwrap_or_else(|_| Err(anyhow!("A lambdananas job panicked")))
+                    })
+                    .collect()
+            })
+        };
+
+        // Merged in the order of the groups, so the output doesn't depend on which job ends first
+        let mut build_system_output = Vec::new();
+        let mut all_succeeded = true;
+        for output in outputs {
+            let (output, succeeded) = output?;
+
+            build_system_output.extend_from_slice(&output);
+            if !output.ends_with(b"\n") {
+                build_system_output.push(b'\n');
+            }
+            all_succeeded &= succeeded;
+        }
+
+        Ok((build_system_output, all_succeeded))
+    }
+
+    /// Identifies the lambdananas that is run and its arguments, so cached results of another
+    /// version aren't reused
+    fn get_checker_id(&self, options: &RunOptions) -> String {
+        let checker = match *self {
+            Self::Default { ref lambdananas } => {
+                let path = if lambdananas.contains('/') {
+                    Some(PathBuf::from(lambdananas))
+                } else {
+                    shared::find_in_path(lambdananas)
+                };
+                let checksum = path.and_then(|path| sha256::try_digest(path).ok());
+
+                format!("{} {}", lambdananas, checksum.unwrap_or_default())
+            }
+            Self::Container {
+                ref runtime,
+                ref image,
+            } => {
+                // The id changes when the tag is moved to a new image
+                let id = Command::new(runtime)
+                    .args(["image", "inspect", "--format", "{{.Id}}", image])
+                    .output()
+                    .ok()
+                    .filter(|output| output.status.success())
+                    .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string());
+
+                format!("{} {}", image, id.unwrap_or_default())
+            }
+        };
+
+        format!("{}\n{}", checker, options.lambdananas_args.join("\n"))
+    }
+
+    /// Runs lambdananas on paths, job telling apart the processes started by the same build.
+    /// Returns its output, and whether it succeeded.
+    fn check(&self, paths: &[String], job: usize, options: &RunOptions) -> Result<(Vec<u8>, bool)> {
+        let output = match *self {
+            Self::Default { ref lambdananas } => {
+                let mut command = Command::new(lambdananas);
+                command.args(&options.lambdananas_args).args(paths);
+
+                let (output, stopped) = match run(
+                    &mut command,
+                    options.timeout,
+                    options.memory_limit,
+                    kill_tree,
+                ) {
+                    Ok(result) => result,
+                    Err(e) if e.kind() == ErrorKind::NotFound => {
+                        return Err(RunError::Missing(lambdananas.clone()).into())
+                    }
+                    Err(e) => return Err(e.into()),
+                };
 
-        let build_system_output = match *self {
-            Self::Default => {
-                let command = Command::new("lambdananas").arg(".").output()?;
+                check_stopped(lambdananas, paths, &output, stopped, options)?;
+                check_output(lambdananas, output, options.keep_going)?
+            }
+            Self::Container {
+                ref runtime,
+                ref image,
+            } => {
+                let project = env::current_dir()?;
+                let name = format!("cs2-haskell-{}-{}", std::process::id(), job);
+                let mut command = Command::new(runtime);
 
-                if !command.status.success() {
-                    return Err(anyhow!("Couldn't run lambdananas"));
+                command
+                    .args(["run", "--rm", "--name", &name, "-v"])
+                    .arg(format!("{}:/project:ro", project.display()));
+                if let Some(memory_limit) = options.memory_limit {
+                    // The runtime enforces it, swap included so it can't be dodged
+                    let memory = format!("{}m", memory_limit);
+                    command.args(["--memory", &memory, "--memory-swap", &memory]);
                 }
+                command
+                    .args(["-w", "/project", image, "lambdananas"])
+                    .args(&options.lambdananas_args)
+                    .args(paths);
+
+                // Killing the client would leave the container running
+                let (output, mut stopped) = run(&mut command, options.timeout, None, |child| {
+                    let killed = Command::new(runtime)
+                        .args(["kill", &name])
+                        .stdout(Stdio::null())
+                        .stderr(Stdio::null())
+                        .status();
+                    if !killed.is_ok_and(|status| status.success()) {
+                        kill_tree(child);
+                    }
+                })?;
 
-                command.stdout
+                // Exit code of the container runtime when the command isn't in the image
+                if output.status.code() == Some(127) {
+                    return Err(RunError::Missing(format!("lambdananas in {}", image)).into());
+                }
+                // Exit code of the container when the runtime killed it for its memory
+                if stopped.is_none()
+                    && options.memory_limit.is_some()
+                    && output.status.code() == Some(MEMORY_EXIT_CODE)
+                {
+                    stopped = Some(Stopped::OutOfMemory);
+                }
+
+                let program = format!("lambdananas in {}", image);
+                check_stopped(&program, paths, &output, stopped, options)?;
+                check_output(&program, output, options.keep_going)?
             }
         };
 
-        shared::split_output(build_system_output)
+        Ok(output)
+    }
+}
+
+/// Files Haskell files are generated from, and the tool generating them
+const GENERATOR_SOURCES: [(&str, &str); 5] = [
+    ("y", "Happy"),
+    ("ly", "Happy"),
+    ("x", "Alex"),
+    ("hsc", "hsc2hs"),
+    ("chs", "c2hs"),
+];
+
+/// Haskell files that aren't tracked by git, or every one outside a git repository
+fn find_untracked_files() -> Vec<String> {
+    let output = Command::new("git")
+        .args(["ls-files", "--others", "--", "*.hs"])
+        .stderr(Stdio::null())
+        .output();
+
+    match output {
+        Ok(output) if output.status.success() => String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .map(String::from)
+            .collect(),
+        // Not in a git repository
+        _ => {
+            let mut files = Vec::new();
+            find_haskell_files(Path::new("."), &mut files);
+            files
+                .iter()
+                .map(|file| shared::skip_leading_dot(file).to_string())
+                .collect()
+        }
+    }
+}
+
+/// Source file and tool file was generated from, found next to it or in its LINE pragma
+fn find_generator(file: &Path) -> Option<(String, &'static str)> {
+    for (extension, tool) in GENERATOR_SOURCES {
+        let source = file.with_extension(extension);
+
+        if source.exists() {
+            return Some((source.to_string_lossy().to_string(), tool));
+        }
+    }
+
+    // Happy and Alex point back to their source, like {-# LINE 1 "src/Parser.y" #-}
+    let content = fs::read(file).ok()?;
+    let head = String::from_utf8_lossy(&content[..content.len().min(2048)]).to_string();
+    static LINE_PRAGMA: OnceLock<Regex> = OnceLock::new();
+    let re = LINE_PRAGMA.get_or_init(|| {
+        Regex::new(r#"\{-# LINE \d+ "([^"]+)\.(\w+)" #-\}"#)
+            .expect("the LINE pragma pattern is a valid regex")
+    });
+
+    for (_, [name, extension]) in re.captures_iter(&head).map(|c| c.extract()) {
+        if let Some((_, tool)) = GENERATOR_SOURCES
+            .iter()
+            .find(|(source_extension, _)| *source_extension == extension)
+        {
+            return Some((format!("{}.{}", name, extension), tool));
+        }
+    }
+    None
+}
+
+/// Untracked Haskell files that were generated, with what they were generated from
+fn find_generated_files() -> Vec<(String, String)> {
+    let mut generated = Vec::new();
+
+    for file in find_untracked_files() {
+        // Build artifacts are never checked anyway
+        if Path::new(&file)
+            .components()
+            .any(|component| is_skipped_dir(&component.as_os_str().to_string_lossy()))
+        {
+            continue;
+        }
+
+        if let Some((source, tool)) = find_generator(Path::new(&file)) {
+            generated.push((file, format!("{} by {}", source, tool)));
+        }
+    }
+    generated
+}
+
+/// Looks for Haskell files generated by Happy, Alex and the like, which aren't worth checking.
+/// Removes them if asked to, returns the ones that are left otherwise.
+pub fn clean_generated_files(remove: bool) -> Result<Vec<String>> {
+    let generated = find_generated_files();
+
+    if generated.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    if remove {
+        for (file, source) in &generated {
+            fs::remove_file(file)?;
+            println!("Removed {}, generated from {}", file, source);
+        }
+        return Ok(Vec::new());
+    }
+
+    println!(
+        "Leaving out {} generated file(s), use --remove-generated to delete them:",
+        generated.len()
+    );
+    for (file, source) in &generated {
+        println!("  {} (generated from {})", file, source);
+    }
+    Ok(generated.into_iter().map(|(file, _)| file).collect())
+}
+
+/// Directories that aren't part of the sources, git's and the build artifacts
+fn is_skipped_dir(name: &str) -> bool {
+    name == ".git" || ARTIFACT_DIRS.contains(&name)
+}
+
+/// Keeps the output lines of the files that are checked when lambdananas is run by cs2-haskell,
+/// so that its output is the same when it is given with --input
+pub fn filter_output(
+    lines: Vec<String>,
+    project: Option<&Project>,
+    generated: &[String],
+) -> Vec<String> {
+    lines
+        .into_iter()
+        .filter(|line| {
+            let Some(file) = shared::get_output_file(line) else {
+                return true;
+            };
+
+            // lambdananas checks generated files anyway when it is given their directory
+            !Path::new(file)
+                .components()
+                .any(|component| is_skipped_dir(&component.as_os_str().to_string_lossy()))
+                && !generated.iter().any(|generated| generated == file)
+                && project.map_or(true, |project| project.find_component(file).is_some())
+        })
+        .collect()
+}
+
+/// Haskell files in the source directories of project
+fn get_project_files(project: &Project) -> Vec<String> {
+    let mut files = Vec::new();
+
+    for dir in project.get_source_dirs() {
+        find_haskell_files(Path::new(&dir), &mut files);
+    }
+    // Components can share source directories
+    files.sort();
+    files.dedup();
+    files
+}
+
+/// Number of Haskell files under path, used to balance the jobs
+fn count_haskell_files(path: &Path) -> usize {
+    if !path.is_dir() {
+        return usize::from(path.extension().is_some_and(|extension| extension == "hs"));
+    }
+
+    match fs::read_dir(path) {
+        Ok(entries) => entries
+            .flatten()
+            .filter(|entry| {
+                !entry
+                    .file_type()
+                    .is_ok_and(|file_type| file_type.is_symlink())
+                    && !is_skipped_dir(&entry.file_name().to_string_lossy())
+            })
+            .map(|entry| count_haskell_files(&entry.path()))
+            .sum(),
+        Err(_) => 0,
+    }
+}
+
+/// Adds the Haskell files under path to files, named like lambdananas would
+fn find_haskell_files(path: &Path, files: &mut Vec<String>) {
+    let Ok(entries) = fs::read_dir(path) else {
+        return;
+    };
+
+    let mut entries = entries.flatten().collect::<Vec<_>>();
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
+        let Ok(file_type) = entry.file_type() else {
+            continue;
+        };
+
+        if file_type.is_dir() && !is_skipped_dir(&entry.file_name().to_string_lossy()) {
+            find_haskell_files(&entry.path(), files);
+        } else if file_type.is_file() && entry.path().extension().is_some_and(|ext| ext == "hs") {
+            files.push(entry.path().to_string_lossy().to_string());
+        }
     }
+}
+
+/// Splits files into at most jobs groups of about the same size
+fn split_files(files: Vec<String>, jobs: usize) -> Vec<Vec<String>> {
+    let mut groups = vec![Vec::new(); jobs.clamp(1, files.len().max(1))];
+    let count = groups.len();
 
-    fn clean(&self) -> Result<()> {
-        Ok(())
+    for (i, file) in files.into_iter().enumerate() {
+        groups[i % count].push(file);
     }
+    groups
+}
+
+/// Splits the top-level entries of the project into at most jobs groups with about as many
+/// Haskell files each, leaving out the build artifacts. With one job and nothing to leave out,
+/// the whole project is a single group.
+fn split_project(jobs: usize) -> Result<Vec<Vec<String>>> {
+    let whole_project = vec![vec![String::from(".")]];
+    // lambdananas would check the build artifacts if it was given the whole project
+    let has_artifacts = ARTIFACT_DIRS.iter().any(|dir| Path::new(dir).exists());
+
+    if jobs <= 1 && !has_artifacts {
+        return Ok(whole_project);
+    }
+
+    let mut entries = Vec::new();
+    for entry in fs::read_dir(".")? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().to_string();
+
+        if is_skipped_dir(&name) {
+            continue;
+        }
+
+        let count = count_haskell_files(&entry.path());
+        if count > 0 {
+            entries.push((format!("./{}", name), count));
+        }
+    }
+
+    if entries.is_empty() {
+        return Ok(Vec::new());
+    }
+    if entries.len() == 1 && !has_artifacts {
+        return Ok(whole_project);
+    }
+
+    // Biggest entries first, each one given to the group with the fewest files so far
+    entries.sort_by(|(a_name, a_count), (b_name, b_count)| {
+        b_count.cmp(a_count).then(a_name.cmp(b_name))
+    });
+
+    let mut groups = vec![(Vec::new(), 0); jobs.clamp(1, entries.len())];
+    for (name, count) in entries {
+        if let Some((paths, total)) = groups.iter_mut().min_by_key(|(_, total)| *total) {
+            paths.push(name);
+            *total += count;
+        }
+    }
+
+    Ok(groups.into_iter().map(|(paths, _)| paths).collect())
+}
+
+fn describe_status(status: &ExitStatus) -> String {
+    if let Some(code) = status.code() {
+        return format!("exit code {}", code);
+    }
+
+    match status.signal() {
+        Some(6) => String::from("aborted, SIGABRT"),
+        Some(9) => String::from("killed, SIGKILL"),
+        Some(11) => String::from("segmentation fault, SIGSEGV"),
+        Some(15) => String::from("terminated, SIGTERM"),
+        Some(signal) => format!("killed by signal {}", signal),
+        None => String::from("unknown status"),
+    }
+}
+
+/// Last lines of stderr, indented under the error message
+fn format_stderr(stderr: &str) -> String {
+    let lines = stderr.lines().collect::<Vec<_>>();
+    let start = lines.len().saturating_sub(STDERR_MAX_LINES);
+
+    lines[start..]
+        .iter()
+        .map(|line| format!("\n    {}", line))
+        .collect()
+}
+
+/// Haskell files lambdananas reported it couldn't parse
+fn find_parse_errors(output: &str) -> Vec<String> {
+    static PARSE_ERROR: OnceLock<Regex> = OnceLock::new();
+    let re = PARSE_ERROR.get_or_init(|| {
+        Regex::new(r"(?mi)^(?:\./)?(\S+\.hs)\b.*parse error")
+            .expect("the parse error pattern is a valid regex")
+    });
+    let mut files = Vec::new();
+
+    for (_, [file]) in re.captures_iter(output).map(|c| c.extract()) {
+        if !files.contains(&file.to_string()) {
+            files.push(file.to_string());
+        }
+    }
+    files
+}
+
+/// Returns stdout if program succeeded, or a description of what went wrong.
+/// With keep_going, the failure is printed and stdout is returned anyway, along with false.
+fn check_output(program: &str, output: Output, keep_going: bool) -> Result<(Vec<u8>, bool)> {
+    if output.status.success() {
+        return Ok((output.stdout, true));
+    }
+
+    let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let parse_errors = find_parse_errors(&format!("{}\n{}", stderr, stdout));
+
+    let program = program.to_string();
+    let error = if !parse_errors.is_empty() {
+        RunError::Parse(program, parse_errors.join(", "), format_stderr(&stderr))
+    } else if output.status.signal().is_some() {
+        RunError::Crashed(
+            program,
+            describe_status(&output.status),
+            format_stderr(&stderr),
+        )
+    } else {
+        RunError::Failed(
+            program,
+            describe_status(&output.status),
+            format_stderr(&stderr),
+        )
+    };
+
+    if !keep_going {
+        return Err(error.into());
+    }
+
+    println!("{}", error);
+    println!("Continuing with what was printed before failing");
+    Ok((output.stdout, false))
 }
 
-pub fn verify_packages() -> bool {
-    let packages = [Packages::Lambdananas];
+/// Reads everything from pipe on another thread, so the child never blocks on a full pipe
+fn read_in_background<R: Read + Send + 'static>(pipe: Option<R>) -> JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut buffer = Vec::new();
 
-    for package in packages {
-        let mut found = false;
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut buffer);
+        }
+        buffer
+    })
+}
+
+/// Resident memory of a process in kilobytes, from /proc/<pid>/status
+fn get_memory_usage(pid: u32) -> Option<u64> {
+    let status = fs::read_to_string(format!("/proc/{}/status", pid)).ok()?;
+
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix("VmRSS:"))?
+        .split_whitespace()
+        .next()?
+        .parse()
+        .ok()
+}
+
+/// Processes started by pid, and the ones they started, from /proc/<pid>/stat
+fn get_descendants(pid: u32) -> Vec<u32> {
+    let mut parents = Vec::new();
+
+    if let Ok(entries) = fs::read_dir("/proc") {
+        for entry in entries.flatten() {
+            let Ok(child) = entry.file_name().to_string_lossy().parse::<u32>() else {
+                continue;
+            };
+            let Ok(stat) = fs::read_to_string(entry.path().join("stat")) else {
+                continue;
+            };
+            // The command name is in parentheses and may contain spaces
+            let parent = stat
+                .rsplit_once(')')
+                .and_then(|(_, rest)| rest.split_whitespace().nth(1))
+                .and_then(|parent| parent.parse::<u32>().ok());
 
-        for path in package.get_packages() {
-            if Path::new(path).exists() {
-                found = true;
+            if let Some(parent) = parent {
+                parents.push((child, parent));
             }
         }
+    }
 
-        if !found {
-            println!("Couldn't find {}", package);
-            return false;
+    let mut descendants = Vec::new();
+    let mut pending = vec![pid];
+    while let Some(current) = pending.pop() {
+        for (child, _) in parents.iter().filter(|(_, parent)| *parent == current) {
+            descendants.push(*child);
+            pending.push(*child);
         }
     }
-    true
+    descendants
+}
+
+/// Kills child and everything it started, which would otherwise keep its output open
+fn kill_tree(child: &mut Child) {
+    let descendants = get_descendants(child.id());
+
+    let _ = child.kill();
+    if !descendants.is_empty() {
+        let _ = Command::new("kill")
+            .arg("-KILL")
+            .args(descendants.iter().map(|pid| pid.to_string()))
+            .stderr(Stdio::null())
+            .status();
+    }
 }
 
-pub fn find() -> Result<Vec<String>> {
-    BuildSystems::Default.build()
+/// Resident memory of a process and every process it started in kilobytes,
+/// as lambdananas may not do all the work itself
+fn get_tree_memory_usage(pid: u32) -> u64 {
+    get_descendants(pid)
+        .into_iter()
+        .chain([pid])
+        .filter_map(get_memory_usage)
+        .sum()
+}
+
+/// Runs command like Command::output, calling stop on it when it runs for longer than timeout
+/// seconds or uses more than memory_limit megabytes
+fn run(
+    command: &mut Command,
+    timeout: Option<u64>,
+    memory_limit: Option<u64>,
+    stop: impl Fn(&mut Child),
+) -> io::Result<(Output, Option<Stopped>)> {
+    let mut child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    let stdout = read_in_background(child.stdout.take());
+    let stderr = read_in_background(child.stderr.take());
+    let deadline = timeout.map(|timeout| Instant::now() + Duration::from_secs(timeout));
+    let mut stopped = None;
+
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+
+        if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            stopped = Some(Stopped::TimedOut);
+        } else if memory_limit.is_some_and(|limit| get_tree_memory_usage(child.id()) > limit * 1024)
+        {
+            stopped = Some(Stopped::OutOfMemory);
+        }
+
+        if stopped.is_some() {
+            stop(&mut child);
+            break child.wait()?;
+        }
+        thread::sleep(POLL_INTERVAL);
+    };
+
+    let output = Output {
+        status,
+        stdout: stdout.join().unwrap_or_default(),
+        stderr: stderr.join().unwrap_or_default(),
+    };
+    Ok((output, stopped))
+}
+
+/// Paths given to lambdananas, and the last file it reported before being stopped
+fn describe_progress(paths: &[String], stdout: &[u8]) -> String {
+    let stdout = String::from_utf8_lossy(stdout);
+    let last_file = stdout
+        .lines()
+        .rev()
+        .find_map(|line| line.split_once(".hs:").map(|(file, _)| file));
+
+    match last_file {
+        Some(file) => format!(
+            "{} (last reported file: {}.hs)",
+            paths.join(" "),
+            shared::skip_leading_dot(file)
+        ),
+        None => format!("{} (no file reported yet)", paths.join(" ")),
+    }
+}
+
+/// Returns an error if lambdananas was killed for going over the limits of options
+fn check_stopped(
+    program: &str,
+    paths: &[String],
+    output: &Output,
+    stopped: Option<Stopped>,
+    options: &RunOptions,
+) -> Result<()> {
+    let progress = || describe_progress(paths, &output.stdout);
+
+    match stopped {
+        None => Ok(()),
+        Some(Stopped::TimedOut) => Err(RunError::TimedOut(
+            program.to_string(),
+            options.timeout.unwrap_or_default(),
+            progress(),
+        )
+        .into()),
+        Some(Stopped::OutOfMemory) => Err(RunError::OutOfMemory(
+            program.to_string(),
+            options.memory_limit.unwrap_or_default(),
+            progress(),
+        )
+        .into()),
+    }
+}
+
+/// Exit code for an error returned by find, so CI can tell a killed lambdananas apart from
+/// style errors
+pub fn get_exit_code(error: &anyhow::Error) -> i32 {
+    match error.downcast_ref::<RunError>() {
+        Some(RunError::TimedOut(..)) => TIMEOUT_EXIT_CODE,
+        Some(RunError::OutOfMemory(..)) => MEMORY_EXIT_CODE,
+        _ => 1,
+    }
+}
+
+/// Checks that the executable that will be run exists
+pub fn verify_packages(lambdananas: &str) -> bool {
+    // A bare name is looked up in PATH, like Command does
+    let found = if lambdananas.contains('/') {
+        Path::new(lambdananas).exists()
+    } else {
+        shared::find_in_path(lambdananas).is_some()
+    };
+
+    if !found {
+        println!("Couldn't find {}", lambdananas);
+    }
+    found
+}
+
+/// Returns true if runtime can run containers, which needs its daemon to be running
+/// and reachable by the current user for docker
+fn is_usable_runtime(runtime: &str) -> bool {
+    shared::find_in_path(runtime).is_some()
+        && Command::new(runtime)
+            .arg("info")
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .is_ok_and(|status| status.success())
+}
+
+fn find_container_runtime() -> Option<String> {
+    CONTAINER_RUNTIMES
+        .iter()
+        .find(|runtime| is_usable_runtime(runtime))
+        .map(|runtime| runtime.to_string())
+}
+
+/// Runs lambdananas in a container if asked to and a runtime is available,
+/// from the host otherwise. Only the source directories of project are checked.
+pub fn find(
+    options: &RunOptions,
+    project: Option<&Project>,
+    generated: &[String],
+) -> Result<Vec<String>> {
+    if options.container || options.container_image.is_some() {
+        match find_container_runtime() {
+            Some(runtime) => {
+                let image = options
+                    .container_image
+                    .clone()
+                    .unwrap_or(String::from(DEFAULT_CONTAINER_IMAGE));
+
+                return BuildSystems::Container { runtime, image }
+                    .check_project(options, project, generated);
+            }
+            None => println!(
+                "Couldn't find {}, running lambdananas from the host",
+                CONTAINER_RUNTIMES.join(" or ")
+            ),
+        }
+    }
+
+    let lambdananas = shared::get_lambdananas(options.lambdananas.as_deref());
+    if !verify_packages(&lambdananas) {
+        return Err(anyhow!(
+            "Some packages seem to not be installed, make sure you ran cs2-haskell install before"
+        ));
+    }
+
+    BuildSystems::Default { lambdananas }.check_project(options, project, generated)
 }
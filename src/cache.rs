@@ -0,0 +1,126 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Result};
+
+use crate::check_cache::RESULTS_DIR;
+
+/// A downloaded release stored as <cache>/<package>/<tag>-<sha256>
+pub struct CacheEntry {
+    pub package: String,
+    pub tag: String,
+    pub checksum: String,
+    pub path: PathBuf,
+    pub size: u64,
+}
+
+/// $XDG_CACHE_HOME/cs2-haskell, or ~/.cache/cs2-haskell
+pub fn get_cache_dir() -> Result<PathBuf> {
+    if let Ok(dir) = env::var("XDG_CACHE_HOME") {
+        if !dir.is_empty() {
+            return Ok(Path::new(&dir).join("cs2-haskell"));
+        }
+    }
+
+    match env::var("HOME") {
+        Ok(home) => Ok(Path::new(&home).join(".cache").join("cs2-haskell")),
+        Err(_) => Err(anyhow!(
+            "Couldn't find the cache directory, HOME is not set"
+        )),
+    }
+}
+
+fn read_entries(package_dir: &Path, package: &str) -> Result<Vec<CacheEntry>> {
+    let mut entries = Vec::new();
+
+    for file in fs::read_dir(package_dir)? {
+        let file = file?;
+        let file_name = file.file_name().to_string_lossy().to_string();
+
+        // Tags can contain dashes, but the checksum never does
+        let Some((tag, checksum)) = file_name.rsplit_once('-') else {
+            continue;
+        };
+
+        entries.push(CacheEntry {
+            package: package.to_string(),
+            tag: tag.to_string(),
+            checksum: checksum.to_string(),
+            path: file.path(),
+            size: file.metadata()?.len(),
+        });
+    }
+    Ok(entries)
+}
+
+pub fn list() -> Result<Vec<CacheEntry>> {
+    let cache_dir = get_cache_dir()?;
+    let mut entries = Vec::new();
+
+    if !cache_dir.exists() {
+        return Ok(entries);
+    }
+
+    for package_dir in fs::read_dir(&cache_dir)? {
+        let package_dir = package_dir?;
+
+        if !package_dir.file_type()?.is_dir() || package_dir.file_name() == RESULTS_DIR {
+            continue;
+        }
+        let package = package_dir.file_name().to_string_lossy().to_string();
+        entries.append(&mut read_entries(&package_dir.path(), &package)?);
+    }
+
+    entries.sort_by(|a, b| (&a.package, &a.tag).cmp(&(&b.package, &b.tag)));
+    Ok(entries)
+}
+
+/// Copies the cached release of package at tag into destination.
+/// Returns false if there is no valid entry for it.
+pub fn restore(package: &str, tag: &str, destination: &str) -> Result<bool> {
+    let package_dir = get_cache_dir()?.join(package);
+
+    if !package_dir.exists() {
+        return Ok(false);
+    }
+
+    for entry in read_entries(&package_dir, package)? {
+        if entry.tag != tag {
+            continue;
+        }
+
+        // A corrupted entry is dropped and downloaded again
+        if sha256::try_digest(&entry.path)? != entry.checksum {
+            fs::remove_file(&entry.path)?;
+            continue;
+        }
+
+        fs::copy(&entry.path, destination)?;
+        return Ok(true);
+    }
+    Ok(false)
+}
+
+pub fn store(package: &str, tag: &str, source: &str) -> Result<()> {
+    let package_dir = get_cache_dir()?.join(package);
+    let checksum = sha256::try_digest(Path::new(source))?;
+
+    fs::create_dir_all(&package_dir)?;
+    fs::copy(source, package_dir.join(format!("{}-{}", tag, checksum)))?;
+    Ok(())
+}
+
+/// Removes the cached releases of package, or the whole cache if None
+pub fn clean(package: Option<&str>) -> Result<()> {
+    let mut path = get_cache_dir()?;
+
+    if let Some(package) = package {
+        path = path.join(package);
+    }
+
+    if path.exists() {
+        fs::remove_dir_all(&path)?;
+    }
+    Ok(())
+}
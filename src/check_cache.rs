@@ -0,0 +1,130 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::Result;
+
+use crate::{cache, shared};
+
+/// Directory of the cache holding the results of lambdananas instead of releases
+pub const RESULTS_DIR: &str = "results";
+
+/// What lambdananas printed for files it already checked, stored as
+/// <cache>/results/<checker>/<sha256 of the path and content of the file>
+pub struct CheckCache {
+    dir: PathBuf,
+}
+
+impl CheckCache {
+    /// Cache of the results of checker, which identifies lambdananas, its version and arguments
+    pub fn open(checker: &str) -> Result<Self> {
+        Ok(Self {
+            dir: cache::get_cache_dir()?
+                .join(RESULTS_DIR)
+                .join(sha256::digest(checker)),
+        })
+    }
+
+    /// Key of file as it is now, changing with its content
+    pub fn key(&self, file: &str) -> Result<String> {
+        let mut data = shared::skip_leading_dot(file).as_bytes().to_vec();
+
+        data.push(0);
+        data.append(&mut fs::read(file)?);
+        Ok(sha256::digest(data))
+    }
+
+    /// Output lines of lambdananas for the file with key, if it was checked before
+    pub fn get(&self, key: &str) -> Option<Vec<String>> {
+        let content = fs::read_to_string(self.dir.join(key)).ok()?;
+
+        Some(content.lines().map(String::from).collect())
+    }
+
+    /// Stores the output lines about each of files, checked when they had keys.
+    /// Files that changed while lambdananas was running are left out, and nothing is
+    /// stored when a line can't be told apart as being about one of files.
+    pub fn store(&self, files: &[(String, String)], lines: &[String]) -> Result<()> {
+        let mut lines_by_file: HashMap<&str, Vec<&str>> = HashMap::new();
+
+        for line in lines.iter().filter(|line| !line.trim().is_empty()) {
+            let file = shared::get_output_file(line).filter(|file| {
+                files
+                    .iter()
+                    .any(|(checked, _)| shared::skip_leading_dot(checked) == *file)
+            });
+
+            match file {
+                Some(file) => lines_by_file.entry(file).or_default().push(line),
+                // Caching the files it may be about without it would lose an error
+                None => return Ok(()),
+            }
+        }
+
+        fs::create_dir_all(&self.dir)?;
+        for (file, key) in files {
+            if self.key(file).ok().as_ref() != Some(key) {
+                continue;
+            }
+
+            let content: String = lines_by_file
+                .get(shared::skip_leading_dot(file))
+                .map(|lines| lines.iter().map(|line| format!("{}\n", line)).collect())
+                .unwrap_or_default();
+            fs::write(self.dir.join(key), content)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    /// Cache in the temporary directory with a file to check, unique to the test
+    fn create_cache(name: &str, file_name: &str) -> (CheckCache, PathBuf, String) {
+        let dir = env::temp_dir().join(format!("cs2-haskell-test-{}-{}", name, std::process::id()));
+
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("sources")).unwrap();
+        let file = dir.join("sources").join(file_name);
+        fs::write(&file, "main = pure ()\n").unwrap();
+
+        let cache = CheckCache {
+            dir: dir.join("results"),
+        };
+        (cache, dir, file.to_string_lossy().to_string())
+    }
+
+    #[test]
+    fn lines_of_paths_with_spaces_are_stored() {
+        let (cache, dir, file) = create_cache("cache-spaces", "My File.hs");
+        let key = cache.key(&file).unwrap();
+        let line = format!("{}:1: MAJOR:H-G1 # bad header", file);
+
+        cache
+            .store(
+                &[(file.clone(), key.clone())],
+                &[line.clone(), String::new()],
+            )
+            .unwrap();
+        let cached = cache.get(&key);
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(cached, Some(vec![line]));
+    }
+
+    #[test]
+    fn nothing_is_stored_when_a_line_is_not_attributed() {
+        let (cache, dir, file) = create_cache("cache-unattributed", "A.hs");
+        let key = cache.key(&file).unwrap();
+        let lines = [String::from("./elsewhere/B.hs:1: MAJOR:H-G1 # bad header")];
+
+        cache.store(&[(file.clone(), key.clone())], &lines).unwrap();
+        let cached = cache.get(&key);
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(cached, None);
+    }
+}
@@ -36,7 +36,11 @@ impl Ci {
                         print!("col={},", nb)
                     }
 
-                    print!("title={} [{}]::", error.level, error.rule);
+                    print!("title={} [{}]", error.level, error.rule);
+                    if let Some(component) = &error.component {
+                        print!(" in {}", component);
+                    }
+                    print!("::");
                     println!("{}", error.description);
                 }
             }
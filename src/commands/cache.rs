@@ -0,0 +1,35 @@
+use anyhow::Result;
+use std::str::FromStr;
+
+use crate::{cache, package::Packages};
+
+pub fn list() -> Result<()> {
+    let entries = cache::list()?;
+
+    if entries.is_empty() {
+        println!("The cache is empty");
+        return Ok(());
+    }
+
+    for entry in entries {
+        println!(
+            "{} {} ({} bytes, sha256 {})",
+            entry.package, entry.tag, entry.size, entry.checksum
+        );
+        println!("  {}", entry.path.display());
+    }
+    Ok(())
+}
+
+pub fn clean(package: &Option<String>) -> Result<()> {
+    if let Some(package_str) = package {
+        let package = Packages::from_str(package_str)?;
+        cache::clean(Some(package.as_str()))?;
+        println!("Cleaned the cache of {}", package);
+        return Ok(());
+    }
+
+    cache::clean(None)?;
+    println!("Cleaned {}", cache::get_cache_dir()?.display());
+    Ok(())
+}
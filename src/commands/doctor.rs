@@ -0,0 +1,202 @@
+use anyhow::{anyhow, Result};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use crate::{
+    commands::shared::get_final_path,
+    package::Packages,
+    shared::{self, find_in_path, Colors, LAMBDANANAS_ENV_VAR},
+    state,
+};
+
+#[derive(Default)]
+struct Report {
+    problems: u32,
+}
+
+impl Report {
+    fn ok(&self, message: &str) {
+        println!("{}[ok]{} {}", Colors::BLUE, Colors::RESET, message);
+    }
+
+    fn problem(&mut self, message: &str, fix: &str) {
+        self.problems += 1;
+        println!("{}[!!]{} {}", Colors::RED, Colors::RESET, message);
+        println!("     {}fix: {}{}", Colors::GRAY, fix, Colors::RESET);
+    }
+}
+
+/// Runs `<path> --version` and returns the first line it printed
+fn get_version(path: &Path) -> Result<Option<String>> {
+    let output = Command::new(path).arg("--version").output()?;
+
+    if !output.status.success() {
+        return Ok(None);
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .next()
+        .map(|line| line.trim().to_string()))
+}
+
+fn check_lambdananas(report: &mut Report, lambdananas: Option<&str>) {
+    let package = Packages::Lambdananas;
+    let managed_path = get_final_path(package.as_str());
+
+    if Path::new(&managed_path).exists() {
+        report.ok(&format!("managed {} is at {}", package, managed_path));
+    } else {
+        report.problem(
+            &format!("managed {} is not installed at {}", package, managed_path),
+            "run cs2-haskell install --package lambdananas",
+        );
+    }
+
+    // The executable cs2-haskell runs, which may not be the managed one
+    let lambdananas = shared::get_lambdananas(lambdananas);
+    let resolved = if lambdananas.contains('/') {
+        Some(PathBuf::from(&lambdananas)).filter(|path| path.exists())
+    } else {
+        find_in_path(&lambdananas)
+    };
+    let configured = lambdananas != package.as_str();
+
+    let Some(resolved) = resolved else {
+        if configured {
+            report.problem(
+                &format!("{} can't be found", lambdananas),
+                &format!(
+                    "fix the path given with --lambdananas or {}",
+                    LAMBDANANAS_ENV_VAR
+                ),
+            );
+        } else {
+            report.problem(
+                &format!("{} can't be found in PATH", package),
+                "add /usr/local/bin to your PATH environment variable",
+            );
+        }
+        return;
+    };
+
+    report.ok(&format!(
+        "{} resolves to {}",
+        lambdananas,
+        resolved.display()
+    ));
+
+    let same_file = match (fs::canonicalize(&resolved), fs::canonicalize(&managed_path)) {
+        (Ok(resolved), Ok(managed)) => resolved == managed,
+        _ => false,
+    };
+    if configured {
+        report.ok(&format!(
+            "{} is used instead of the managed {}, set with --lambdananas or {}",
+            lambdananas, package, LAMBDANANAS_ENV_VAR
+        ));
+    } else if !same_file {
+        report.problem(
+            &format!(
+                "{} in PATH isn't the one managed by cs2-haskell ({})",
+                resolved.display(),
+                managed_path
+            ),
+            &format!(
+                "remove {} or put /usr/local/bin first in your PATH",
+                resolved.display()
+            ),
+        );
+    }
+
+    if !shared::is_executable(&resolved) {
+        report.problem(
+            &format!("{} is not executable", resolved.display()),
+            &format!("run sudo chmod +x {}", resolved.display()),
+        );
+        return;
+    }
+
+    match get_version(&resolved) {
+        Ok(Some(version)) => report.ok(&format!("{} runs, version {}", package, version)),
+        Ok(None) => report.ok(&format!("{} runs, version unknown", package)),
+        Err(e) => report.problem(
+            &format!("{} can't be run: {}", resolved.display(), e),
+            "run cs2-haskell update --package lambdananas --force",
+        ),
+    }
+
+    if let Some(record) = state::read_record(package.as_str(), "installed") {
+        report.ok(&format!(
+            "recorded version {} (sha256 {})",
+            record.version, record.checksum
+        ));
+    }
+}
+
+fn check_git(report: &mut Report) -> bool {
+    match Command::new("git").arg("--version").output() {
+        Ok(output) if output.status.success() => {
+            report.ok(String::from_utf8_lossy(&output.stdout).trim());
+            true
+        }
+        _ => {
+            report.problem(
+                "git is not available",
+                "install git with your package manager",
+            );
+            false
+        }
+    }
+}
+
+fn check_checkout(report: &mut Report) {
+    let path = get_final_path(Packages::Cs2Haskell.as_str());
+
+    if !Path::new(&path).join(".git").exists() {
+        report.problem(
+            &format!("{} is not a git checkout of cs2-haskell", path),
+            "reinstall cs2-haskell with install.sh",
+        );
+        return;
+    }
+
+    match Command::new("git")
+        .args(["-C", &path, "status", "--porcelain"])
+        .output()
+    {
+        Ok(output) if output.status.success() => {
+            if output.stdout.is_empty() {
+                report.ok(&format!("{} is a clean git checkout", path));
+            } else {
+                report.problem(
+                    &format!("{} has local changes", path),
+                    "commit them, or use cs2-haskell update --stash to keep them when updating",
+                );
+            }
+        }
+        _ => report.problem(
+            &format!("Couldn't read the git status of {}", path),
+            &format!("run git -C {} status to see what is wrong", path),
+        ),
+    }
+}
+
+/// lambdananas is the executable given with --lambdananas, if any
+pub fn handler(lambdananas: Option<&str>) -> Result<()> {
+    let mut report = Report::default();
+
+    check_lambdananas(&mut report, lambdananas);
+    if check_git(&mut report) {
+        check_checkout(&mut report);
+    }
+
+    if report.problems > 0 {
+        return Err(anyhow!("{} problem(s) found", report.problems));
+    }
+
+    println!("Everything looks good!");
+    Ok(())
+}
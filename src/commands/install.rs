@@ -2,24 +2,34 @@ use std::str::FromStr;
 
 use anyhow::Result;
 
-use crate::{commands::shared::get_final_path, package::Packages, shared::create_directory};
+use crate::{
+    commands::shared::get_final_path,
+    package::{PackageSource, Packages},
+    shared::create_directory,
+};
 
-fn install_all() -> Result<()> {
+fn install_all(source: &PackageSource) -> Result<()> {
     let all_packages = [Packages::Lambdananas];
 
     for package in all_packages {
-        package.install()?;
+        package.install(source)?;
     }
     Ok(())
 }
 
-pub fn handler(package: &Option<String>) -> Result<()> {
+pub fn handler(
+    package: &Option<String>,
+    from_file: &Option<String>,
+    mirror: &Option<String>,
+) -> Result<()> {
+    let source = PackageSource::new(from_file, mirror);
+
     create_directory(get_final_path("").as_str())?;
 
     if let Some(package_str) = package {
         let package = Packages::from_str(package_str)?;
-        return package.install();
+        return package.install(&source);
     }
 
-    install_all()
+    install_all(&source)
 }
@@ -1,3 +1,8 @@
+pub mod cache;
+pub mod doctor;
 pub mod install;
+pub mod rollback;
 pub mod shared;
+pub mod status;
+pub mod uninstall;
 pub mod update;
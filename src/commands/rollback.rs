@@ -0,0 +1,8 @@
+use anyhow::Result;
+use std::str::FromStr;
+
+use crate::package::Packages;
+
+pub fn handler(package: &str) -> Result<()> {
+    Packages::from_str(package)?.rollback()
+}
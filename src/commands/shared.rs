@@ -1,8 +1,13 @@
-use anyhow::Result;
-use std::env;
+use anyhow::{anyhow, Result};
+use std::{
+    env,
+    path::Path,
+    process::{self, Command},
+};
 
+/// Unique to this process, so concurrent installs don't write to the same file
 pub fn get_temp_path(package: &str) -> String {
-    format!("/tmp/cs2-haskell-{}", package)
+    format!("/tmp/cs2-haskell-{}-{}", package, process::id())
 }
 
 pub fn get_final_path(package: &str) -> String {
@@ -22,3 +27,19 @@ pub fn warn_path_var(directory: &str) -> Result<()> {
     }
     Ok(())
 }
+
+/// Removes a file or directory with sudo privileges
+pub fn remove_path(path: &str) -> Result<()> {
+    if !Path::new(path).exists() {
+        return Ok(());
+    }
+
+    if !Command::new("sudo")
+        .args(["rm", "-rf", path])
+        .status()?
+        .success()
+    {
+        return Err(anyhow!("Impossible to remove {}", path));
+    }
+    Ok(())
+}
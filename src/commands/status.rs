@@ -0,0 +1,52 @@
+use anyhow::Result;
+
+use crate::{
+    commands::update::is_newer,
+    package::{Packages, UNKNOWN_VERSION},
+    shared::Colors,
+};
+
+fn print_status(package: &Packages) -> Result<()> {
+    println!("{}{}:{}", Colors::BOLD, package, Colors::RESET);
+
+    let Some(record) = package.get_installed_record()? else {
+        println!("  not installed");
+        return Ok(());
+    };
+
+    println!("  path:      {}", package.get_installed_paths().join(", "));
+    println!("  installed: {}", record.version);
+    println!("  sha256:    {}", record.checksum);
+
+    let latest = match package.get_latest_version() {
+        Ok(latest) => latest,
+        Err(e) => {
+            println!("  latest:    unknown ({})", e);
+            return Ok(());
+        }
+    };
+    println!("  latest:    {}", latest);
+
+    if record.version == UNKNOWN_VERSION {
+        println!("  update:    unknown, installed version wasn't recorded");
+    } else if is_newer(&latest, &record.version) {
+        println!(
+            "  update:    {}available{} (cs2-haskell update --package {})",
+            Colors::ORANGE,
+            Colors::RESET,
+            package
+        );
+    } else {
+        println!("  update:    up to date");
+    }
+    Ok(())
+}
+
+pub fn handler() -> Result<()> {
+    let packages = [Packages::Cs2Haskell, Packages::Lambdananas];
+
+    for package in packages {
+        print_status(&package)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,66 @@
+use anyhow::Result;
+use std::{path::Path, str::FromStr};
+
+use crate::{
+    commands::shared::{get_final_path, remove_path},
+    package::Packages,
+    shared::confirm,
+};
+
+fn uninstall_all(yes: bool) -> Result<()> {
+    let packages = [Packages::Lambdananas, Packages::Cs2Haskell];
+    let share_path = get_final_path("");
+
+    let mut paths = packages
+        .iter()
+        .flat_map(|package| package.get_installed_paths())
+        .collect::<Vec<_>>();
+    paths.push(share_path.clone());
+    paths.retain(|path| Path::new(path).exists());
+
+    if paths.is_empty() {
+        println!("Nothing to uninstall");
+        return Ok(());
+    }
+
+    println!("The following will be removed:");
+    for path in &paths {
+        println!("  {}", path);
+    }
+    if !yes && !confirm("Continue?")? {
+        println!("Aborted");
+        return Ok(());
+    }
+
+    for package in packages {
+        if package.is_installed() {
+            package.uninstall()?;
+        }
+    }
+    remove_path(&share_path)
+}
+
+pub fn handler(package: &Option<String>, yes: bool) -> Result<()> {
+    if let Some(package_str) = package {
+        let package = Packages::from_str(package_str)?;
+
+        if !package.is_installed() {
+            return package.uninstall();
+        }
+
+        println!("The following will be removed:");
+        for path in package.get_installed_paths() {
+            if Path::new(&path).exists() {
+                println!("  {}", path);
+            }
+        }
+        if !yes && !confirm("Continue?")? {
+            println!("Aborted");
+            return Ok(());
+        }
+
+        return package.uninstall();
+    }
+
+    uninstall_all(yes)
+}
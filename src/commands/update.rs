@@ -1,41 +1,382 @@
 use anyhow::{anyhow, Result};
-use std::{process::Command, str::FromStr};
+use std::{
+    env, fmt, fs,
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+    process::{self, Command},
+    str::FromStr,
+};
 
-use crate::package::Packages;
+use crate::{
+    commands::shared::get_temp_path,
+    github::{self, Asset, Release},
+    migrations,
+    package::{download_file, PackageSource, Packages, UpdateOptions},
+    state,
+};
 
+/// Runs git in the repository at path, returning its trimmed stdout
+fn git(path: &str, args: &[&str]) -> Result<String> {
+    let output = Command::new("git").args(["-C", path]).args(args).output()?;
+
+    if !output.status.success() {
+        // Some commands, like stash pop, report conflicts on stdout
+        let message = if output.stderr.is_empty() {
+            output.stdout
+        } else {
+            output.stderr
+        };
+        return Err(anyhow!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&message).trim()
+        ));
+    }
+    Ok(String::from_utf8(output.stdout)?.trim().to_string())
+}
+
+/// Branch origin/HEAD points to, main if it can't be found
+fn get_default_branch(path: &str) -> String {
+    // ref: refs/heads/main	HEAD
+    if let Ok(output) = git(path, &["ls-remote", "--symref", "origin", "HEAD"]) {
+        if let Some(branch) = output
+            .lines()
+            .find_map(|line| line.strip_prefix("ref: refs/heads/"))
+            .and_then(|line| line.split_whitespace().next())
+        {
+            return branch.to_string();
+        }
+    }
+
+    match git(
+        path,
+        &["symbolic-ref", "--short", "refs/remotes/origin/HEAD"],
+    ) {
+        Ok(branch) => branch.trim_start_matches("origin/").to_string(),
+        Err(_) => String::from("main"),
+    }
+}
+
+/// Tracked files with uncommitted changes, untracked files are left alone
+fn get_local_changes(path: &str) -> Result<Vec<String>> {
+    let status = git(path, &["status", "--porcelain", "--untracked-files=no"])?;
+
+    Ok(status.lines().map(String::from).collect())
+}
+
+/// What the cs2-haskell checkout follows when updating
+#[derive(Clone, PartialEq)]
+pub enum Channel {
+    /// Latest tagged release
+    Stable,
+    /// Tip of the default branch
+    Nightly,
+    /// Tip of a specific branch
+    Branch(String),
+}
+
+impl FromStr for Channel {
+    type Err = anyhow::Error;
+
+    fn from_str(input: &str) -> Result<Self> {
+        match input {
+            "" => Err(anyhow!("The channel can't be empty")),
+            "stable" => Ok(Self::Stable),
+            "nightly" => Ok(Self::Nightly),
+            branch => Ok(Self::Branch(branch.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for Channel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Stable => write!(f, "stable"),
+            Self::Nightly => write!(f, "nightly"),
+            Self::Branch(branch) => write!(f, "{}", branch),
+        }
+    }
+}
+
+/// Channel given on the command line, or the one used by the last update
+pub fn get_channel(channel: &Option<String>) -> Result<Option<Channel>> {
+    let channel = match channel {
+        Some(channel) => Some(channel.clone()),
+        None => state::read_setting(Packages::Cs2Haskell.as_str(), "channel"),
+    };
+
+    channel
+        .map(|channel| Channel::from_str(&channel))
+        .transpose()
+}
+
+/// Latest tag of origin, sorted as versions
+fn get_latest_tag(path: &str) -> Result<String> {
+    git(path, &["fetch", "--tags", "origin"])?;
+
+    match git(path, &["tag", "--list", "--sort=-v:refname"])?
+        .lines()
+        .next()
+    {
+        Some(tag) => Ok(tag.to_string()),
+        None => Err(anyhow!("{} has no tagged release", path)),
+    }
+}
+
+/// Returns true if rev has commits that can't be found on origin or in a tag
+fn has_local_commits(path: &str, rev: &str) -> Result<bool> {
+    let count = git(
+        path,
+        &["rev-list", "--count", rev, "--not", "--remotes", "--tags"],
+    )?;
+
+    Ok(count != "0")
+}
+
+/// Updates the checkout at path to what the channel follows.
+/// Local changes and commits are never thrown away silently: uncommitted
+/// changes can be stashed and reapplied or discarded after listing them,
+/// and local commits are kept on a backup branch before moving away from them.
 /// Returns true if project needs to be rebuilt, false if it's already at the latest version
-pub fn pull_repo(path: &str, package: &str) -> Result<bool> {
-    let command = format!("cd {} && git pull origin main", path);
-    let results = Command::new("sh").args(["-c", &command]).output()?;
-
-    if !results.status.success() {
-        let command = format!(
-            "cd {} && git reset --hard main && git pull origin main",
-            path
+pub fn pull_repo(
+    path: &str,
+    package: &str,
+    force: bool,
+    stash: bool,
+    discard: bool,
+    channel: &Channel,
+) -> Result<bool> {
+    // None when following a tag, which is checked out as a detached HEAD
+    let (branch, upstream) = match channel {
+        Channel::Stable => {
+            let tag = get_latest_tag(path)?;
+            println!("Following release {}", tag);
+            (
+                None,
+                git(path, &["rev-parse", &format!("{}^{{commit}}", tag)])?,
+            )
+        }
+        Channel::Nightly | Channel::Branch(_) => {
+            let branch = match channel {
+                Channel::Branch(branch) => branch.clone(),
+                _ => get_default_branch(path),
+            };
+            git(path, &["fetch", "origin", &branch])?;
+            let upstream = git(path, &["rev-parse", "FETCH_HEAD"])?;
+            (Some(branch), upstream)
+        }
+    };
+
+    let head = git(path, &["rev-parse", "HEAD"])?;
+    let current_branch = git(path, &["symbolic-ref", "--quiet", "--short", "HEAD"]).ok();
+
+    if head == upstream && current_branch == branch {
+        return Ok(false);
+    }
+
+    // The branch we're switching to might have local commits too
+    let mut revs = vec![head.clone()];
+    if let Some(branch) = &branch {
+        let local_ref = format!("refs/heads/{}", branch);
+        if current_branch.as_ref() != Some(branch)
+            && git(path, &["rev-parse", "--verify", "--quiet", &local_ref]).is_ok()
+        {
+            revs.push(git(path, &["rev-parse", &local_ref])?);
+        }
+    }
+
+    for rev in revs {
+        if !has_local_commits(path, &rev)? {
+            continue;
+        }
+        if !force {
+            return Err(anyhow!(
+                "{} has local commits that aren't upstream, update them manually or use --force to switch to {} (your commits will be kept on a backup branch)",
+                path,
+                channel
+            ));
+        }
+
+        let backup = format!("cs2-haskell-backup-{}", &rev[..rev.len().min(12)]);
+        git(path, &["branch", "-f", &backup, &rev])?;
+        println!(
+            "Your local commits of {} are kept on branch {}",
+            package, backup
         );
-        let results = Command::new("sh").args(["-c", &command]).output()?;
+    }
 
-        if !results.status.success() {
+    let local_changes = get_local_changes(path)?;
+    let mut stashed = false;
+    let mut discarded = false;
+    if !local_changes.is_empty() {
+        if stash {
+            git(path, &["stash", "push", "-m", "cs2-haskell update"])?;
+            stashed = true;
+        } else if discard {
+            println!(
+                "Discarding the local changes of {}:\n{}",
+                path,
+                local_changes.join("\n")
+            );
+            discarded = true;
+        } else {
             return Err(anyhow!(
-                "Had problems updating {}: {}",
+                "{} has local changes:\n{}\nUse --stash to reapply them after updating or --discard-changes to discard them",
+                path,
+                local_changes.join("\n")
+            ));
+        }
+    }
+
+    // Local commits were backed up above, so moving the branch is safe
+    let mut checkout = vec!["checkout", "--quiet"];
+    if discarded {
+        checkout.push("--force");
+    }
+    match &branch {
+        Some(branch) => checkout.extend(["-B", branch, &upstream]),
+        None => checkout.extend(["--detach", &upstream]),
+    }
+    git(path, &checkout)?;
+
+    if stashed {
+        if let Err(e) = git(path, &["stash", "pop"]) {
+            return Err(anyhow!(
+                "Updated {} but couldn't reapply your local changes, they are kept in git stash: {}",
                 package,
-                String::from_utf8(results.stderr)?
+                e
             ));
         }
+    }
+
+    Ok(head != upstream)
+}
+
+/// Splits a version such as v1.2.3 into its numbers
+fn parse_version(version: &str) -> Vec<u64> {
+    version
+        .trim_start_matches('v')
+        .split('.')
+        .map(|part| {
+            let digits = part
+                .chars()
+                .take_while(|c| c.is_ascii_digit())
+                .collect::<String>();
+            digits.parse().unwrap_or(0)
+        })
+        .collect()
+}
+
+/// Returns true if version is more recent than other, like a release
+/// compared to the installed version
+pub fn is_newer(version: &str, other: &str) -> bool {
+    parse_version(version) > parse_version(other)
+}
+
+/// Checksum of the asset, either computed by GitHub or published as <asset>.sha256
+fn get_expected_checksum(release: &Release, asset: &Asset) -> Result<String> {
+    if let Some(checksum) = asset
+        .digest
+        .as_ref()
+        .and_then(|digest| digest.strip_prefix("sha256:"))
+    {
+        return Ok(checksum.to_string());
+    }
+
+    let checksum_name = format!("{}.sha256", asset.name);
+    let Ok(checksum_asset) = release.find_asset(&checksum_name) else {
+        return Err(anyhow!(
+            "No checksum published for {}, refusing to install it",
+            asset.name
+        ));
     };
 
-    if String::from_utf8(results.stdout)?.contains("Already up to date.") {
-        Ok(false)
-    } else {
-        Ok(true)
+    let checksum_path = get_temp_path(&checksum_name);
+    download_file(&checksum_asset.browser_download_url, &checksum_path)?;
+    let content = fs::read_to_string(&checksum_path)?;
+    _ = fs::remove_file(&checksum_path);
+
+    match content.split_whitespace().next() {
+        Some(checksum) => Ok(checksum.to_ascii_lowercase()),
+        None => Err(anyhow!("{} is empty", checksum_name)),
     }
 }
 
-fn update_all(force: bool) -> Result<()> {
+/// Moves new_binary over the running executable.
+/// The rename is atomic, so the executable is never seen half-written.
+fn replace_executable(new_binary: &str) -> Result<PathBuf> {
+    let executable = env::current_exe()?;
+    // Unique to this process, so concurrent updates don't write to the same file
+    let staging = executable.with_file_name(format!(".cs2-haskell.new-{}", process::id()));
+
+    let moved = fs::copy(new_binary, &staging)
+        .and_then(|_| fs::set_permissions(&staging, fs::Permissions::from_mode(0o755)))
+        .and_then(|_| fs::rename(&staging, &executable));
+    if moved.is_ok() {
+        return Ok(executable);
+    }
+    _ = fs::remove_file(&staging);
+
+    // Not writable by the current user, e.g. /usr/local/bin
+    let staging = staging.to_string_lossy().to_string();
+    let executable_str = executable.to_string_lossy().to_string();
+    if !Command::new("sudo")
+        .args(["install", "-m755", new_binary, &staging])
+        .status()?
+        .success()
+        || !Command::new("sudo")
+            .args(["mv", "-f", &staging, &executable_str])
+            .status()?
+            .success()
+    {
+        return Err(anyhow!("Impossible to replace {}", executable_str));
+    }
+    Ok(executable)
+}
+
+/// Replaces the running cs2-haskell with the binary of the latest release,
+/// after checking that the release is newer and that its checksum matches
+pub fn update_from_release(release_api: &str, force: bool) -> Result<()> {
+    let release = github::latest_release(release_api)?;
+    let current_version = env!("CARGO_PKG_VERSION");
+
+    if !force && parse_version(&release.tag_name) <= parse_version(current_version) {
+        println!("Nothing to update");
+        return Ok(());
+    }
+
+    let asset_name = format!("cs2-haskell-{}-{}", env::consts::OS, env::consts::ARCH);
+    let asset = release.find_asset(&asset_name)?;
+    let expected_checksum = get_expected_checksum(&release, asset)?;
+    let temp_path = get_temp_path(&asset_name);
+
+    download_file(&asset.browser_download_url, &temp_path)?;
+
+    if sha256::try_digest(Path::new(&temp_path))? != expected_checksum {
+        _ = fs::remove_file(&temp_path);
+        return Err(anyhow!(
+            "Checksum of {} doesn't match the release, not installing it",
+            asset_name
+        ));
+    }
+
+    let executable = replace_executable(&temp_path)?;
+    _ = fs::remove_file(&temp_path);
+
+    println!(
+        "Updated {} from {} to {}",
+        executable.display(),
+        current_version,
+        release.tag_name
+    );
+    Ok(())
+}
+
+fn update_all(options: &UpdateOptions) -> Result<()> {
     let packages = [Packages::Cs2Haskell, Packages::Lambdananas];
 
     for package in packages {
-        if let Err(e) = package.update(force) {
+        if let Err(e) = package.update(options) {
             if package == Packages::Cs2Haskell {
                 println!("{}", e);
             } else {
@@ -48,18 +389,70 @@ fn update_all(force: bool) -> Result<()> {
 
 /// Does cleanup work, checks if there are files that shouldn't be there,
 /// or should be moved and such.
-/// Doesn't actually remove them for you, but suggests that they can be removed.
-fn pre_update() -> Result<()> {
+/// Doesn't actually remove them for you unless apply is set, but suggests that they can be removed.
+fn pre_update(apply: bool) -> Result<()> {
+    let migrations = migrations::find_all()?;
+
+    if migrations.is_empty() {
+        return Ok(());
+    }
+
+    println!("Found files that shouldn't be there:");
+    for migration in &migrations {
+        println!("  {}: {}", migration.reason, migration.action);
+    }
+
+    let (manual, automatic): (Vec<_>, Vec<_>) = migrations
+        .into_iter()
+        .partition(|migration| migration.manual);
+
+    if !manual.is_empty() {
+        println!("Other lambdananas binaries are never removed by --apply, remove the ones you don't use yourself");
+    }
+
+    if automatic.is_empty() {
+        return Ok(());
+    }
+
+    if !apply {
+        println!("Run cs2-haskell update --apply to clean up the leftovers");
+        return Ok(());
+    }
+
+    for migration in automatic {
+        migration.apply()?;
+        println!("Done: {}", migration.action);
+    }
     Ok(())
 }
 
-pub fn handler(package: &Option<String>, force: bool) -> Result<()> {
-    pre_update()?;
+#[allow(clippy::too_many_arguments)]
+pub fn handler(
+    package: &Option<String>,
+    force: bool,
+    from_file: &Option<String>,
+    mirror: &Option<String>,
+    prebuilt: bool,
+    stash: bool,
+    discard_changes: bool,
+    channel: &Option<String>,
+    apply: bool,
+) -> Result<()> {
+    let options = UpdateOptions {
+        force,
+        source: PackageSource::new(from_file, mirror),
+        prebuilt,
+        stash,
+        discard_changes,
+        channel: get_channel(channel)?,
+    };
+
+    pre_update(apply)?;
 
     if let Some(package_str) = package {
         let package = Packages::from_str(package_str)?;
-        return package.update(force);
+        return package.update(&options);
     }
 
-    update_all(force)
+    update_all(&options)
 }
@@ -0,0 +1,137 @@
+use std::env;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::Result;
+use reqwest::{
+    blocking::{Client, RequestBuilder, Response},
+    header::{ACCEPT, AUTHORIZATION, USER_AGENT},
+    StatusCode,
+};
+use serde::Deserialize;
+use thiserror::Error;
+
+const CS2_USER_AGENT: &str = "cs2-haskell <https://github.com/hugoschool/cs2-haskell>";
+
+#[derive(Deserialize)]
+pub struct Asset {
+    pub name: String,
+    pub browser_download_url: String,
+    /// Checksum computed by GitHub, e.g. "sha256:<hex>"
+    pub digest: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct Release {
+    pub tag_name: String,
+    pub assets: Vec<Asset>,
+}
+
+/// Body returned by the GitHub API when a request fails
+#[derive(Deserialize)]
+struct ApiError {
+    message: String,
+}
+
+#[derive(Error, Debug)]
+pub enum GitHubError {
+    #[error("GitHub API rate limit exceeded, resets {0} (set GITHUB_TOKEN to raise the limit)")]
+    RateLimited(String),
+
+    #[error("GitHub API returned {0}: {1}")]
+    Api(StatusCode, String),
+
+    #[error("Couldn't find asset {0} in release {1}")]
+    AssetNotFound(String, String),
+}
+
+impl Release {
+    pub fn find_asset(&self, name: &str) -> Result<&Asset> {
+        match self.assets.iter().find(|asset| asset.name == name) {
+            Some(asset) => Ok(asset),
+            None => Err(GitHubError::AssetNotFound(name.to_string(), self.tag_name.clone()).into()),
+        }
+    }
+}
+
+/// Builds a GET request to the GitHub API, authenticated if GITHUB_TOKEN is set
+fn api_request(client: &Client, url: &str) -> RequestBuilder {
+    let request = client
+        .get(url)
+        .header(USER_AGENT, CS2_USER_AGENT)
+        .header(ACCEPT, "application/vnd.github+json");
+
+    match env::var("GITHUB_TOKEN") {
+        Ok(token) if !token.is_empty() => {
+            request.header(AUTHORIZATION, format!("Bearer {}", token))
+        }
+        _ => request,
+    }
+}
+
+fn get_header<'a>(response: &'a Response, name: &str) -> Option<&'a str> {
+    response.headers().get(name)?.to_str().ok()
+}
+
+/// Formats the moment the rate limit will be lifted, based on the response headers
+fn rate_limit_reset(response: &Response) -> String {
+    if let Some(seconds) = get_header(response, "retry-after").and_then(|s| s.parse::<u64>().ok()) {
+        return format!("in {}s", seconds);
+    }
+
+    let Some(reset) = get_header(response, "x-ratelimit-reset").and_then(|s| s.parse::<u64>().ok())
+    else {
+        return String::from("at an unknown time");
+    };
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let remaining = reset.saturating_sub(now);
+
+    format!(
+        "in {}m{:02}s (at {} UNIX time)",
+        remaining / 60,
+        remaining % 60,
+        reset
+    )
+}
+
+fn check_response(response: Response) -> Result<Response> {
+    let status = response.status();
+
+    if status.is_success() {
+        return Ok(response);
+    }
+
+    let rate_limited = status == StatusCode::TOO_MANY_REQUESTS
+        || (status == StatusCode::FORBIDDEN
+            && (get_header(&response, "x-ratelimit-remaining") == Some("0")
+                || get_header(&response, "retry-after").is_some()));
+
+    if rate_limited {
+        return Err(GitHubError::RateLimited(rate_limit_reset(&response)).into());
+    }
+
+    let message = match response.json::<ApiError>() {
+        Ok(error) => error.message,
+        Err(_) => String::from("unknown error"),
+    };
+    Err(GitHubError::Api(status, message).into())
+}
+
+fn fetch_release(client: &Client, api_url: &str) -> Result<Release> {
+    let response = check_response(api_request(client, api_url).send()?)?;
+
+    Ok(response.json::<Release>()?)
+}
+
+pub fn latest_release(api_url: &str) -> Result<Release> {
+    fetch_release(&Client::new(), api_url)
+}
+
+/// Same as latest_release, but gives up after timeout
+pub fn latest_release_timeout(api_url: &str, timeout: Duration) -> Result<Release> {
+    let client = Client::builder().timeout(timeout).build()?;
+
+    fetch_release(&client, api_url)
+}
@@ -0,0 +1,268 @@
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use anyhow::Result;
+use ignore::gitignore::{Gitignore, GitignoreBuilder, Glob};
+use ignore::Match;
+
+use crate::shared;
+
+const GITIGNORE_FILE: &str = ".gitignore";
+/// Read in every directory, its rules winning over the .gitignore ones
+const CS2IGNORE_FILE: &str = ".cs2ignore";
+
+/// Matchers of the ignore files of a directory
+struct DirectoryMatchers {
+    git: Gitignore,
+    cs2: Gitignore,
+}
+
+/// Evaluates the rules of the .gitignore and .cs2ignore files of every directory,
+/// .git/info/exclude and the global excludes file of git.
+/// Like git, only the .cs2ignore rules apply to the files it tracks.
+pub struct IgnoreRules {
+    cwd: PathBuf,
+    /// Root of the git repository, or the current directory outside of one
+    root: PathBuf,
+    /// Files in the index of the repository
+    tracked: HashSet<PathBuf>,
+    directories: HashMap<PathBuf, DirectoryMatchers>,
+    exclude: Gitignore,
+    global: Gitignore,
+}
+
+fn find_repository_root(cwd: &Path) -> Option<PathBuf> {
+    cwd.ancestors()
+        .find(|dir| dir.join(".git").exists())
+        .map(Path::to_path_buf)
+}
+
+/// Files tracked by the repository at root, empty outside of one
+fn find_tracked_files(root: &Path) -> HashSet<PathBuf> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(root)
+        .args(["ls-files", "-z"])
+        .stderr(Stdio::null())
+        .output();
+
+    match output {
+        Ok(output) if output.status.success() => output
+            .stdout
+            .split(|byte| *byte == 0)
+            .filter(|file| !file.is_empty())
+            .map(|file| root.join(String::from_utf8_lossy(file).as_ref()))
+            .collect(),
+        _ => HashSet::new(),
+    }
+}
+
+/// Matcher made of the files of dir, empty if there are none
+fn build_matcher(dir: &Path, files: &[PathBuf]) -> Gitignore {
+    let mut builder = GitignoreBuilder::new(dir);
+
+    for file in files {
+        if file.exists() {
+            // Invalid lines are skipped, like git does
+            let _ = builder.add(file);
+        }
+    }
+    builder.build().unwrap_or_else(|_| Gitignore::empty())
+}
+
+impl IgnoreRules {
+    pub fn new() -> Result<Self> {
+        Ok(Self::with_dir(env::current_dir()?))
+    }
+
+    /// Rules for the files of cwd
+    fn with_dir(cwd: PathBuf) -> Self {
+        let root = find_repository_root(&cwd).unwrap_or(cwd.clone());
+        let exclude = build_matcher(&root, &[root.join(".git").join("info").join("exclude")]);
+        let (global, _) = GitignoreBuilder::new(&root).build_global();
+        let tracked = find_tracked_files(&root);
+
+        Self {
+            cwd,
+            root,
+            tracked,
+            directories: HashMap::new(),
+            exclude,
+            global,
+        }
+    }
+
+    fn get_directory_matchers(&mut self, dir: &Path) -> &DirectoryMatchers {
+        self.directories
+            .entry(dir.to_path_buf())
+            .or_insert_with(|| DirectoryMatchers {
+                git: build_matcher(dir, &[dir.join(GITIGNORE_FILE)]),
+                cs2: build_matcher(dir, &[dir.join(CS2IGNORE_FILE)]),
+            })
+    }
+
+    /// Rule matching path itself, from the most specific source to the least.
+    /// Only the .cs2ignore rules are used without git_rules.
+    fn match_path(&mut self, path: &Path, is_dir: bool, git_rules: bool) -> Option<Match<Glob>> {
+        // Rules of a directory override the ones of its parents
+        let dirs = path
+            .ancestors()
+            .skip(1)
+            .take_while(|dir| dir.starts_with(&self.root))
+            .map(Path::to_path_buf)
+            .collect::<Vec<_>>();
+        for dir in dirs {
+            let matchers = self.get_directory_matchers(&dir);
+            let mut rule = matchers.cs2.matched(path, is_dir);
+
+            if rule.is_none() && git_rules {
+                rule = matchers.git.matched(path, is_dir);
+            }
+            if !rule.is_none() {
+                return Some(rule.map(Glob::clone));
+            }
+        }
+
+        if !git_rules {
+            return None;
+        }
+        for matcher in [&self.exclude, &self.global] {
+            let rule = matcher.matched(path, is_dir);
+
+            if !rule.is_none() {
+                return Some(rule.map(Glob::clone));
+            }
+        }
+        None
+    }
+
+    /// Rule deciding whether file is ignored. Like git, a file can't be re-included
+    /// when one of its parent directories is excluded, and git never ignores
+    /// the files it tracks.
+    fn find_rule(&mut self, file: &str) -> Option<Match<Glob>> {
+        let path = self.cwd.join(shared::skip_leading_dot(file));
+
+        if !path.starts_with(&self.root) {
+            return None;
+        }
+        let git_rules = !self.tracked.contains(&path);
+
+        let mut parents = path
+            .ancestors()
+            .skip(1)
+            .take_while(|dir| dir.starts_with(&self.root) && *dir != self.root)
+            .map(Path::to_path_buf)
+            .collect::<Vec<_>>();
+        parents.reverse();
+        for parent in parents {
+            if let Some(Match::Ignore(glob)) = self.match_path(&parent, true, git_rules) {
+                return Some(Match::Ignore(glob));
+            }
+        }
+
+        self.match_path(&path, false, git_rules)
+    }
+
+    /// Pattern ignoring file and where it comes from, None if file isn't ignored
+    pub fn get_ignore_reason(&mut self, file: &str) -> Option<String> {
+        let Some(Match::Ignore(glob)) = self.find_rule(file) else {
+            return None;
+        };
+
+        match glob.from() {
+            Some(from) => Some(format!(
+                "{} in {}",
+                glob.original(),
+                from.strip_prefix(&self.cwd).unwrap_or(from).display()
+            )),
+            None => Some(glob.original().to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    /// Empty git repository in the temporary directory, unique to the test
+    fn create_repository(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("cs2-haskell-test-{}-{}", name, std::process::id()));
+
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join(".git")).unwrap();
+        dir
+    }
+
+    #[test]
+    fn excluded_directory_cannot_be_reincluded() {
+        let dir = create_repository("reinclude");
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        fs::write(dir.join(".gitignore"), "sub/\n").unwrap();
+        fs::write(dir.join("sub").join(".gitignore"), "!keep.hs\n").unwrap();
+
+        let mut rules = IgnoreRules::with_dir(dir.clone());
+        let reason = rules.get_ignore_reason("sub/keep.hs");
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(reason, Some(String::from("sub/ in .gitignore")));
+    }
+
+    #[test]
+    fn tracked_files_are_only_ignored_by_cs2ignore() {
+        let dir = create_repository("tracked");
+        fs::remove_dir_all(dir.join(".git")).unwrap();
+        fs::create_dir_all(dir.join("src")).unwrap();
+        fs::write(dir.join("src").join("A.hs"), "").unwrap();
+        fs::write(dir.join("src").join("B.hs"), "").unwrap();
+        let git = |args: &[&str]| {
+            let status = Command::new("git").arg("-C").arg(&dir).args(args).output();
+            assert!(status.unwrap().status.success(), "git {:?} failed", args);
+        };
+        git(&["init", "--quiet"]);
+        git(&["add", "src/A.hs", "src/B.hs"]);
+        fs::write(dir.join(".gitignore"), "src/\n").unwrap();
+        fs::write(dir.join(".cs2ignore"), "B.hs\n").unwrap();
+        fs::write(dir.join("src").join("C.hs"), "").unwrap();
+
+        let mut rules = IgnoreRules::with_dir(dir.clone());
+        let tracked = rules.get_ignore_reason("./src/A.hs");
+        let cs2ignored = rules.get_ignore_reason("./src/B.hs");
+        let untracked = rules.get_ignore_reason("./src/C.hs");
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(tracked, None);
+        assert_eq!(cs2ignored, Some(String::from("B.hs in .cs2ignore")));
+        assert_eq!(untracked, Some(String::from("src/ in .gitignore")));
+    }
+
+    #[test]
+    fn deeper_rules_override_parent_ones() {
+        let dir = create_repository("override");
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        fs::write(dir.join(".gitignore"), "*.hs\n").unwrap();
+        fs::write(dir.join("sub").join(".gitignore"), "!keep.hs\n").unwrap();
+
+        let mut rules = IgnoreRules::with_dir(dir.clone());
+        let kept = rules.get_ignore_reason("./sub/keep.hs");
+        let ignored = rules.get_ignore_reason("./sub/other.hs");
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(kept, None);
+        assert_eq!(ignored, Some(String::from("*.hs in .gitignore")));
+    }
+
+    #[test]
+    fn cs2ignore_is_read() {
+        let dir = create_repository("cs2ignore");
+        fs::write(dir.join(".cs2ignore"), "Generated.hs\n").unwrap();
+
+        let mut rules = IgnoreRules::with_dir(dir.clone());
+        let reason = rules.get_ignore_reason("Generated.hs");
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(reason, Some(String::from("Generated.hs in .cs2ignore")));
+    }
+}
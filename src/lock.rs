@@ -0,0 +1,32 @@
+use std::fs::{File, OpenOptions};
+
+use anyhow::Result;
+use fs2::FileExt;
+
+const LOCK_PATH: &str = "/tmp/cs2-haskell.lock";
+
+/// Held while installing or updating, the lock is released when dropped
+pub struct Lock {
+    _file: File,
+}
+
+/// Waits until no other cs2-haskell process is installing or updating packages
+pub fn acquire() -> Result<Lock> {
+    let file = match OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(LOCK_PATH)
+    {
+        Ok(file) => file,
+        // Created by another user, a read-only handle can still be locked
+        Err(_) => File::open(LOCK_PATH)?,
+    };
+
+    if file.try_lock_exclusive().is_err() {
+        println!("Waiting for another cs2-haskell install or update to finish...");
+        file.lock_exclusive()?;
+    }
+    Ok(Lock { _file: file })
+}
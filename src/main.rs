@@ -1,16 +1,34 @@
 mod build_systems;
+mod cache;
+mod check_cache;
 mod ci;
 mod commands;
+mod github;
+mod ignore_rules;
+mod lock;
+mod migrations;
 mod package;
 mod parse;
+mod project;
 mod shared;
+mod state;
+mod update_check;
 
 use ci::Ci;
 use clap::{Parser, Subcommand};
-use std::{
-    io::{BufRead, IsTerminal},
-    str::FromStr,
-};
+use std::{io::IsTerminal, str::FromStr};
+
+#[derive(Subcommand)]
+enum CacheSubcommand {
+    /// Lists the downloaded releases
+    List,
+    /// Removes the downloaded releases
+    Clean {
+        /// Only clean a certain package
+        #[arg(long)]
+        package: Option<String>,
+    },
+}
 
 #[derive(Subcommand)]
 enum ArgSubcommand {
@@ -19,6 +37,14 @@ enum ArgSubcommand {
         /// Only install a certain package
         #[arg(long)]
         package: Option<String>,
+
+        /// Install from a local binary instead of downloading it
+        #[arg(long, conflicts_with = "mirror")]
+        from_file: Option<String>,
+
+        /// Download from a mirror serving <url>/<package> instead of GitHub
+        #[arg(long)]
+        mirror: Option<String>,
     },
     /// Update cs2 and the dependencies
     Update {
@@ -26,9 +52,63 @@ enum ArgSubcommand {
         #[arg(long)]
         package: Option<String>,
 
-        /// Force update even if there is nothing new when fetching
+        /// Force update even if there is nothing new when fetching,
+        /// moving away from local commits of the cs2-haskell checkout
         #[arg(short, long)]
         force: bool,
+
+        /// Stash local changes of the cs2-haskell checkout and reapply them after updating
+        #[arg(long)]
+        stash: bool,
+
+        /// Discard local changes of the cs2-haskell checkout, listing them first
+        #[arg(long, conflicts_with = "stash")]
+        discard_changes: bool,
+
+        /// Follow stable (tagged releases), nightly (default branch) or a branch, remembered for next updates
+        #[arg(long)]
+        channel: Option<String>,
+
+        /// Clean up the leftovers found before updating instead of only listing them
+        #[arg(long)]
+        apply: bool,
+
+        /// Update from a local binary instead of downloading it
+        #[arg(long, conflicts_with = "mirror")]
+        from_file: Option<String>,
+
+        /// Download from a mirror serving <url>/<package> instead of GitHub
+        #[arg(long)]
+        mirror: Option<String>,
+
+        /// Update cs2-haskell from the release binaries instead of building it
+        #[arg(long)]
+        prebuilt: bool,
+    },
+    /// Reinstalls the version that was replaced by the last update
+    Rollback {
+        /// Package to roll back
+        #[arg(long)]
+        package: String,
+    },
+    /// Removes the installed packages
+    Uninstall {
+        /// Only uninstall a certain package
+        #[arg(long)]
+        package: Option<String>,
+
+        /// Don't ask for confirmation before removing files
+        #[arg(short, long)]
+        yes: bool,
+    },
+    /// Shows the installed and latest versions of the packages
+    Status,
+    /// Diagnoses the installation and suggests fixes
+    Doctor,
+    /// Manage the download cache
+    Cache {
+        #[command(subcommand)]
+        command: CacheSubcommand,
     },
 }
 
@@ -38,21 +118,123 @@ struct Args {
     #[command(subcommand)]
     command: Option<ArgSubcommand>,
 
+    /// Read the output of lambdananas from a file, or from stdin with -, instead of running it
+    #[arg(long, value_name = "FILE")]
+    input: Option<String>,
+
     /// Prints the errors in a correct way for the specified platform
     #[arg(long)]
     ci: Option<String>,
 
-    /// Disable checking for files ignored by git
+    /// Disable checking for files ignored by git or .cs2ignore
     #[arg(long)]
     no_ignore: bool,
+
+    /// List the ignored errors, grouped by the rule that ignored them
+    #[arg(long, conflicts_with = "no_ignore")]
+    show_ignored: bool,
+
+    /// Show the errors under their file, rule or level, with the number of errors of each rule or level
+    #[arg(long, value_name = "file|rule|level", default_value = "file")]
+    group_by: parse::GroupBy,
+
+    /// Order of the groups, by name (severity for levels) or with the most errors first
+    #[arg(long, value_name = "name|count", default_value = "name")]
+    sort: parse::SortBy,
+
+    /// Don't check for new versions of cs2-haskell and lambdananas
+    #[arg(long)]
+    no_update_check: bool,
+
+    /// Run lambdananas in a container (docker or podman) instead of from the host
+    #[arg(long)]
+    container: bool,
+
+    /// Container image providing lambdananas, implies --container
+    #[arg(long)]
+    container_image: Option<String>,
+
+    /// Path to the lambdananas executable, defaults to CS2_LAMBDANANAS or the one in PATH
+    #[arg(long, conflicts_with_all = ["container", "container_image"])]
+    lambdananas: Option<String>,
+
+    /// Show the errors lambdananas found even if it failed
+    #[arg(long)]
+    keep_going: bool,
+
+    /// Kill lambdananas after this many seconds, exiting with code 124
+    #[arg(long, value_name = "SECS")]
+    timeout: Option<u64>,
+
+    /// Kill lambdananas when it uses more than this many megabytes, exiting with code 137
+    #[arg(long, value_name = "MB")]
+    memory_limit: Option<u64>,
+
+    /// Run this many lambdananas processes at once, each checking part of the top-level directories
+    #[arg(short, long, value_name = "N", default_value_t = 1)]
+    jobs: usize,
+
+    /// Only check the files that changed since the last run, the others' errors coming from the cache
+    #[arg(long)]
+    incremental: bool,
+
+    /// Delete the untracked Haskell files generated by Happy, Alex, hsc2hs or c2hs instead of only leaving them out
+    #[arg(long)]
+    remove_generated: bool,
+
+    /// Extra arguments given to lambdananas, after --
+    #[arg(last = true)]
+    lambdananas_args: Vec<String>,
 }
 
 fn main() {
     let args = Args::parse();
 
     match &args.command {
-        Some(ArgSubcommand::Install { package }) => {
-            match commands::install::handler(package) {
+        Some(ArgSubcommand::Install {
+            package,
+            from_file,
+            mirror,
+        }) => {
+            match commands::install::handler(package, from_file, mirror) {
+                Ok(_) => {}
+                Err(e) => {
+                    println!("{}", e);
+                    std::process::exit(1);
+                }
+            };
+        }
+        Some(ArgSubcommand::Update {
+            package,
+            force,
+            from_file,
+            mirror,
+            prebuilt,
+            stash,
+            discard_changes,
+            channel,
+            apply,
+        }) => {
+            match commands::update::handler(
+                package,
+                *force,
+                from_file,
+                mirror,
+                *prebuilt,
+                *stash,
+                *discard_changes,
+                channel,
+                *apply,
+            ) {
+                Ok(_) => {}
+                Err(e) => {
+                    println!("{}", e);
+                    std::process::exit(1);
+                }
+            };
+        }
+        Some(ArgSubcommand::Rollback { package }) => {
+            match commands::rollback::handler(package) {
                 Ok(_) => {}
                 Err(e) => {
                     println!("{}", e);
@@ -60,8 +242,8 @@ fn main() {
                 }
             };
         }
-        Some(ArgSubcommand::Update { package, force }) => {
-            match commands::update::handler(package, *force) {
+        Some(ArgSubcommand::Uninstall { package, yes }) => {
+            match commands::uninstall::handler(package, *yes) {
                 Ok(_) => {}
                 Err(e) => {
                     println!("{}", e);
@@ -69,6 +251,35 @@ fn main() {
                 }
             };
         }
+        Some(ArgSubcommand::Status) => {
+            match commands::status::handler() {
+                Ok(_) => {}
+                Err(e) => {
+                    println!("{}", e);
+                    std::process::exit(1);
+                }
+            };
+        }
+        Some(ArgSubcommand::Doctor) => {
+            match commands::doctor::handler(args.lambdananas.as_deref()) {
+                Ok(_) => {}
+                Err(e) => {
+                    println!("{}", e);
+                    std::process::exit(1);
+                }
+            };
+        }
+        Some(ArgSubcommand::Cache { command }) => {
+            let result = match command {
+                CacheSubcommand::List => commands::cache::list(),
+                CacheSubcommand::Clean { package } => commands::cache::clean(package),
+            };
+
+            if let Err(e) = result {
+                println!("{}", e);
+                std::process::exit(1);
+            }
+        }
         &None => {
             let ci: Option<Ci> = if let Some(ci) = args.ci {
                 match Ci::from_str(&ci) {
@@ -82,44 +293,83 @@ fn main() {
                 None
             };
 
-            if !std::io::stdin().is_terminal() && ci.is_none() {
-                let mut full_input = Vec::new();
-                for line in std::io::stdin().lock().lines() {
-                    match line {
-                        Ok(s) => full_input.push(s),
-                        Err(_) => break,
-                    }
+            let update_check = update_check::start(args.no_update_check);
+
+            let project = match project::detect() {
+                Ok(project) => project,
+                Err(e) => {
+                    println!("{}, checking every file.", e);
+                    None
                 }
+            };
 
-                let _ = parse::parse_output(full_input, true, None);
-            } else {
-                if !build_systems::verify_packages() {
-                    println!(
-                        "Some packages seem to not be installed, make sure you ran cs2-haskell install before"
-                    );
+            // Without --input, piped output of lambdananas is read unless running for a CI platform
+            let input = match &args.input {
+                Some(input) => Some(input.as_str()),
+                None if !std::io::stdin().is_terminal() && ci.is_none() => Some("-"),
+                None => None,
+            };
+
+            let generated = match build_systems::clean_generated_files(args.remove_generated) {
+                Ok(generated) => generated,
+                Err(e) => {
+                    println!("{}", e);
                     std::process::exit(1);
                 }
+            };
 
-                let lines = match build_systems::find() {
+            let lines = if let Some(input) = input {
+                match parse::read_input(input) {
                     Ok(lines) => lines,
                     Err(e) => {
                         println!("{}", e);
                         std::process::exit(1);
                     }
+                }
+            } else {
+                let run_options = build_systems::RunOptions {
+                    container: args.container,
+                    container_image: args.container_image.clone(),
+                    lambdananas: args.lambdananas.clone(),
+                    lambdananas_args: args.lambdananas_args.clone(),
+                    keep_going: args.keep_going,
+                    timeout: args.timeout,
+                    memory_limit: args.memory_limit,
+                    jobs: args.jobs,
+                    incremental: args.incremental,
                 };
 
-                match parse::parse_output(lines, args.no_ignore, ci) {
-                    Ok(exit) => {
-                        if exit {
-                            std::process::exit(1);
-                        }
-                    }
+                match build_systems::find(&run_options, project.as_ref(), &generated) {
+                    Ok(lines) => lines,
                     Err(e) => {
                         println!("{}", e);
+                        std::process::exit(build_systems::get_exit_code(&e));
+                    }
+                }
+            };
+            // Same files as when cs2-haskell runs lambdananas itself
+            let lines = build_systems::filter_output(lines, project.as_ref(), &generated);
+
+            match parse::parse_output(
+                lines,
+                args.no_ignore,
+                ci,
+                project.as_ref(),
+                args.show_ignored,
+                args.group_by,
+                args.sort,
+            ) {
+                Ok(exit) => {
+                    update_check::print_hint(update_check, args.no_update_check);
+                    if exit {
                         std::process::exit(1);
                     }
-                };
-            }
+                }
+                Err(e) => {
+                    println!("{}", e);
+                    std::process::exit(1);
+                }
+            };
         }
     }
 }
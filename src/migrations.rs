@@ -0,0 +1,188 @@
+use std::env;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{Duration, SystemTime};
+
+use anyhow::{anyhow, Result};
+
+use crate::commands::shared::{get_final_path, remove_path};
+use crate::package::Packages;
+use crate::shared::{find_all_in_path, LAMBDANANAS_ENV_VAR};
+
+/// Prefix of everything get_temp_path creates
+const TEMP_PREFIX: &str = "cs2-haskell-";
+/// Where the README used to tell to clone cs2-haskell
+const OLD_CHECKOUT_NAME: &str = "cs2";
+/// Temporary files younger than this may still be used, even by a process
+/// that doesn't put its PID in their name, like install.sh
+const MIN_TEMP_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+pub enum Action {
+    Remove(PathBuf),
+    /// Removes a single file, never a directory
+    RemoveFile(PathBuf),
+    Move(PathBuf, PathBuf),
+}
+
+impl Action {
+    fn apply(&self) -> Result<()> {
+        match self {
+            Self::Remove(path) => remove_path(&path.to_string_lossy()),
+            Self::RemoveFile(path) => {
+                if !Command::new("sudo").arg("rm").arg(path).status()?.success() {
+                    return Err(anyhow!("Impossible to remove {}", path.display()));
+                }
+                Ok(())
+            }
+            Self::Move(from, to) => {
+                if !Command::new("sudo")
+                    .arg("mv")
+                    .args([from, to])
+                    .status()?
+                    .success()
+                {
+                    return Err(anyhow!(
+                        "Impossible to move {} to {}",
+                        from.display(),
+                        to.display()
+                    ));
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Remove(path) => write!(f, "remove {}", path.display()),
+            Self::RemoveFile(path) => write!(f, "sudo rm {}", path.display()),
+            Self::Move(from, to) => write!(f, "move {} to {}", from.display(), to.display()),
+        }
+    }
+}
+
+/// Something left behind by an older or interrupted install, and how to clean it up
+pub struct Migration {
+    pub reason: String,
+    pub action: Action,
+    /// Only suggested, never applied by --apply
+    pub manual: bool,
+}
+
+impl Migration {
+    pub fn apply(&self) -> Result<()> {
+        self.action.apply()
+    }
+}
+
+/// Returns true if the temporary file belongs to a cs2-haskell process that is still running
+fn is_in_use(file_name: &str) -> bool {
+    match file_name.rsplit_once('-') {
+        Some((_, pid)) if !pid.is_empty() && pid.chars().all(|c| c.is_ascii_digit()) => {
+            Path::new("/proc").join(pid).exists()
+        }
+        _ => false,
+    }
+}
+
+/// Returns true if the temporary file was modified recently enough to still be used
+fn is_recent(path: &Path) -> bool {
+    let Ok(modified) = fs::symlink_metadata(path).and_then(|metadata| metadata.modified()) else {
+        return true;
+    };
+
+    // A modification time in the future counts as recent
+    SystemTime::now()
+        .duration_since(modified)
+        .map_or(true, |age| age < MIN_TEMP_AGE)
+}
+
+/// Downloads and clones that were never moved into place
+fn find_temp_files() -> Result<Vec<Migration>> {
+    let mut migrations = Vec::new();
+
+    for entry in fs::read_dir("/tmp")? {
+        let entry = entry?;
+        let file_name = entry.file_name().to_string_lossy().to_string();
+
+        // PIDs get reused, so only old enough files are considered left behind
+        if file_name.starts_with(TEMP_PREFIX) && !is_in_use(&file_name) && !is_recent(&entry.path())
+        {
+            migrations.push(Migration {
+                reason: String::from("leftover temporary file"),
+                action: Action::Remove(entry.path()),
+                manual: false,
+            });
+        }
+    }
+    Ok(migrations)
+}
+
+/// Other lambdananas binaries in PATH, which may be run instead of the managed one.
+/// They may have been installed on purpose, so they are only ever suggested for removal.
+fn find_stale_binaries() -> Vec<Migration> {
+    let package = Packages::Lambdananas;
+    let managed_path = fs::canonicalize(get_final_path(package.as_str())).ok();
+    let configured_path = env::var(LAMBDANANAS_ENV_VAR)
+        .ok()
+        .and_then(|path| fs::canonicalize(path).ok());
+    let mut seen = Vec::new();
+    let mut migrations = Vec::new();
+
+    for path in find_all_in_path(package.as_str()) {
+        let Ok(canonical) = fs::canonicalize(&path) else {
+            continue;
+        };
+
+        if Some(&canonical) == managed_path.as_ref()
+            || Some(&canonical) == configured_path.as_ref()
+            || seen.contains(&canonical)
+        {
+            continue;
+        }
+        seen.push(canonical);
+
+        migrations.push(Migration {
+            reason: format!("{} not managed by cs2-haskell", package),
+            action: Action::RemoveFile(path),
+            manual: true,
+        });
+    }
+    migrations
+}
+
+/// Checkouts cloned to /usr/local/share/cs2-haskell/cs2 instead of .../cs2-haskell
+fn find_old_layouts() -> Vec<Migration> {
+    let old_path = Path::new(&get_final_path(OLD_CHECKOUT_NAME)).to_path_buf();
+    let new_path = Path::new(&get_final_path(Packages::Cs2Haskell.as_str())).to_path_buf();
+
+    if !old_path.exists() {
+        return Vec::new();
+    }
+
+    if new_path.exists() {
+        vec![Migration {
+            reason: String::from("duplicate checkout from an old install"),
+            action: Action::Remove(old_path),
+            manual: false,
+        }]
+    } else {
+        vec![Migration {
+            reason: String::from("checkout at the location used by old installs"),
+            action: Action::Move(old_path, new_path),
+            manual: false,
+        }]
+    }
+}
+
+pub fn find_all() -> Result<Vec<Migration>> {
+    let mut migrations = find_temp_files()?;
+
+    migrations.append(&mut find_stale_binaries());
+    migrations.append(&mut find_old_layouts());
+    Ok(migrations)
+}
@@ -1,23 +1,29 @@
 use std::fmt;
+use std::fs;
 use std::path::Path;
-use std::process::Command;
+use std::process::{self, Command};
 use std::str::FromStr;
 
 use anyhow::{anyhow, Ok, Result};
-use regex::Regex;
-use reqwest::{blocking::Client, header::USER_AGENT};
 use thiserror::Error;
 
+use crate::cache;
 use crate::commands::{
-    shared::{get_final_path, get_temp_path, warn_path_var},
-    update::pull_repo,
+    shared::{get_final_path, get_temp_path, remove_path, warn_path_var},
+    update::{pull_repo, update_from_release, Channel},
 };
+use crate::github;
+use crate::lock;
+use crate::shared::find_in_path;
+use crate::state;
 
 const LAMBDANANAS_RELEASE_API: &str =
     "https://api.github.com/repos/Epitech/lambdananas/releases/latest";
-const LAMBDANANAS_RELEASE_LINK: &str =
-    "https://github.com/Epitech/lambdananas/releases/download/$REPLACE/lambdananas";
-const CS2_USER_AGENT: &str = "cs2-haskell <https://github.com/hugoschool/cs2-haskell>";
+const CS2_HASKELL_RELEASE_API: &str =
+    "https://api.github.com/repos/hugoschool/cs2-haskell/releases/latest";
+const LAMBDANANAS_ASSET_NAME: &str = "lambdananas";
+pub const UNKNOWN_VERSION: &str = "unknown";
+const CS2_HASKELL_BINARY: &str = "/usr/local/bin/cs2-haskell";
 
 #[derive(Clone, Debug, PartialEq)]
 pub enum Packages {
@@ -25,6 +31,42 @@ pub enum Packages {
     Lambdananas,
 }
 
+/// Where to fetch a package's binary from
+pub enum PackageSource {
+    /// Latest release from the upstream GitHub repository
+    Release,
+    /// Local copy of the binary, e.g. from a USB stick
+    File(String),
+    /// HTTP mirror serving the binary at <url>/<package>
+    Mirror(String),
+}
+
+impl PackageSource {
+    pub fn new(from_file: &Option<String>, mirror: &Option<String>) -> Self {
+        if let Some(path) = from_file {
+            Self::File(path.clone())
+        } else if let Some(url) = mirror {
+            Self::Mirror(url.trim_end_matches('/').to_string())
+        } else {
+            Self::Release
+        }
+    }
+}
+
+/// Options shared by every package when updating
+pub struct UpdateOptions {
+    pub force: bool,
+    pub source: PackageSource,
+    /// Update cs2-haskell from release binaries instead of building it
+    pub prebuilt: bool,
+    /// Stash local changes of the cs2-haskell checkout and reapply them after updating
+    pub stash: bool,
+    /// Discard local changes of the cs2-haskell checkout, after listing them
+    pub discard_changes: bool,
+    /// What cs2-haskell follows, None keeps the default behavior
+    pub channel: Option<Channel>,
+}
+
 #[derive(Error, Debug)]
 enum PackagesError {
     #[error("Impossible to build {0}")]
@@ -38,29 +80,46 @@ enum PackagesError {
 
     #[error("Already installed, use cs2-haskell update instead")]
     AlreadyInstalled,
+
+    #[error("No previous version of {0} to roll back to")]
+    NoPreviousVersion(Packages),
+
+    #[error("Rolling back isn't supported for {0}")]
+    RollbackUnsupported(Packages),
 }
 
-/// Release link must contain a $REPLACE
-fn download_latest_release(release_url: &str, release_link: &str, temp_path: &str) -> Result<()> {
-    let client = Client::new();
-    let response = client
-        .get(release_url)
-        .header(USER_AGENT, CS2_USER_AGENT)
-        .send()?
-        .text()?;
-    let mut tag_name: Option<&str> = None;
-
-    if let Some((_, [tag])) = Regex::new(r#"tag_name":"(.*?)".*"#)?
-        .captures_iter(&response)
-        .map(|c| c.extract())
-        .next()
-    {
-        tag_name = Some(tag);
+/// Downloads the asset named asset_name from the latest release,
+/// reusing the cached copy if that release was already downloaded
+fn download_latest_release(
+    package: &str,
+    release_url: &str,
+    asset_name: &str,
+    temp_path: &str,
+) -> Result<String> {
+    let release = github::latest_release(release_url)?;
+
+    // The cache only saves a download, so it failing doesn't stop the install
+    let restored = cache::restore(package, &release.tag_name, temp_path).unwrap_or_else(|e| {
+        println!("Couldn't read the cached {}: {}", package, e);
+        false
+    });
+    if restored {
+        println!("Using cached {} {}", package, release.tag_name);
+        return Ok(release.tag_name);
+    }
+
+    let asset = release.find_asset(asset_name)?;
+    download_file(&asset.browser_download_url, temp_path)?;
+
+    if let Err(e) = cache::store(package, &release.tag_name, temp_path) {
+        println!("Couldn't cache {}: {}", package, e);
     }
+    Ok(release.tag_name)
+}
 
-    let new_release_link = &release_link.replace("$REPLACE", tag_name.unwrap());
+pub fn download_file(url: &str, temp_path: &str) -> Result<()> {
     if !Command::new("wget")
-        .args(["-O", temp_path, new_release_link])
+        .args(["-O", temp_path, url])
         .status()?
         .success()
     {
@@ -81,6 +140,21 @@ fn compare_sha_sums(file_one: &String, file_two: &String) -> Result<bool> {
     }
 }
 
+/// Version printed by a cs2-haskell binary, None if it can't be run
+fn get_binary_version(binary_path: &str) -> Option<String> {
+    let output = Command::new(binary_path).arg("--version").output().ok()?;
+
+    if !output.status.success() {
+        return None;
+    }
+    // "cs2-haskell <version>"
+    String::from_utf8(output.stdout)
+        .ok()?
+        .split_whitespace()
+        .nth(1)
+        .map(str::to_string)
+}
+
 impl FromStr for Packages {
     type Err = anyhow::Error;
 
@@ -126,57 +200,245 @@ impl Packages {
                     return Err(anyhow!("Impossible to chmod {}", temp_path));
                 }
 
+                // Renaming is atomic, so a half-written binary is never run
+                let staging_path = format!("{}.new-{}", final_path, process::id());
                 if !Command::new("sudo")
-                    .args(["install", "-Dm755", &temp_path, &final_path])
+                    .args(["install", "-Dm755", &temp_path, &staging_path])
                     .status()?
                     .success()
+                    || !Command::new("sudo")
+                        .args(["mv", "-f", &staging_path, &final_path])
+                        .status()?
+                        .success()
                 {
                     return Err(PackagesError::Install(Self::Lambdananas).into());
                 }
+                _ = fs::remove_file(&temp_path);
             }
         }
         Ok(())
     }
 
-    pub fn get_packages(&self) -> &[&str] {
+    /// Puts the binary of the package at temp_path, from the given source.
+    /// Returns the fetched version, "unknown" if the source doesn't tell.
+    fn fetch(&self, source: &PackageSource, temp_path: &str) -> Result<String> {
+        let mut version = String::from(UNKNOWN_VERSION);
+
+        #[allow(clippy::single_match)]
         match *self {
-            Self::Lambdananas => &["/usr/local/bin/lambdananas"],
-            _ => &[],
+            Self::Lambdananas => match source {
+                PackageSource::Release => {
+                    version = download_latest_release(
+                        self.as_str(),
+                        LAMBDANANAS_RELEASE_API,
+                        LAMBDANANAS_ASSET_NAME,
+                        temp_path,
+                    )?
+                }
+                PackageSource::File(path) => {
+                    if let Err(e) = fs::copy(path, temp_path) {
+                        return Err(anyhow!("Impossible to copy {}: {}", path, e));
+                    }
+                }
+                PackageSource::Mirror(url) => {
+                    download_file(&format!("{}/{}", url, LAMBDANANAS_ASSET_NAME), temp_path)?
+                }
+            },
+            _ => {}
+        }
+        Ok(version)
+    }
+
+    /// Remembers which version of the package is now installed
+    fn record_installed(&self, version: &str) -> Result<()> {
+        let record = state::Record::new(version, &get_final_path(self.as_str()))?;
+
+        state::write_record(self.as_str(), "installed", &record)
+    }
+
+    /// Keeps a copy of the currently installed binary so it can be rolled back to
+    fn backup(&self) -> Result<()> {
+        let package = self.as_str();
+        let final_path = get_final_path(package);
+        let version = match state::read_record(package, "installed") {
+            Some(record) => record.version,
+            None => String::from(UNKNOWN_VERSION),
+        };
+        let backup_path = state::get_backup_path(package)?;
+
+        if let Some(parent) = backup_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::copy(&final_path, &backup_path)?;
+        state::write_record(
+            package,
+            "previous",
+            &state::Record::new(&version, &final_path)?,
+        )
+    }
+
+    pub fn get_release_api(&self) -> &'static str {
+        match *self {
+            Self::Cs2Haskell => CS2_HASKELL_RELEASE_API,
+            Self::Lambdananas => LAMBDANANAS_RELEASE_API,
+        }
+    }
+
+    /// Tag of the latest upstream release
+    pub fn get_latest_version(&self) -> Result<String> {
+        Ok(github::latest_release(self.get_release_api())?.tag_name)
+    }
+
+    /// Version of the installed binary, None if it isn't installed
+    /// or its version is unknown
+    pub fn get_installed_version(&self) -> Option<String> {
+        let package = self.as_str();
+
+        let version = match *self {
+            // The binary may not have been built from the checkout, e.g. after a prebuilt update
+            Self::Cs2Haskell => get_binary_version(CS2_HASKELL_BINARY),
+            Self::Lambdananas => {
+                state::read_record(package, "installed").map(|record| record.version)
+            }
+        };
+        version.filter(|version| version != UNKNOWN_VERSION)
+    }
+
+    /// Version and checksum of the installed binary, None if it isn't installed
+    pub fn get_installed_record(&self) -> Result<Option<state::Record>> {
+        let binary_path = match *self {
+            Self::Cs2Haskell => String::from(CS2_HASKELL_BINARY),
+            Self::Lambdananas => get_final_path(self.as_str()),
+        };
+        if !Path::new(&binary_path).exists() {
+            return Ok(None);
         }
+
+        let version = self
+            .get_installed_version()
+            .unwrap_or_else(|| String::from(UNKNOWN_VERSION));
+        Ok(Some(state::Record::new(&version, &binary_path)?))
     }
 
-    pub fn install(&self) -> Result<()> {
+    pub fn install(&self, source: &PackageSource) -> Result<()> {
         let package = self.as_str();
         let temp_path = get_temp_path(package);
         let final_path = get_final_path(package);
 
+        let _lock = lock::acquire()?;
+
         if Path::new(&final_path).exists() {
             return Err(PackagesError::AlreadyInstalled.into());
         }
 
         println!("Installing {}", package);
 
-        #[allow(clippy::single_match)]
+        let version = self.fetch(source, &temp_path)?;
+        self.build()?;
+        self.record_installed(&version)?;
+        _ = warn_path_var("/usr/local/bin");
+
+        Ok(())
+    }
+
+    /// Paths removed when uninstalling the package
+    pub fn get_installed_paths(&self) -> Vec<String> {
         match *self {
-            Self::Lambdananas => {
-                download_latest_release(
-                    LAMBDANANAS_RELEASE_API,
-                    LAMBDANANAS_RELEASE_LINK,
-                    &temp_path,
-                )?;
-            }
-            _ => {}
+            Self::Cs2Haskell => vec![
+                get_final_path(self.as_str()),
+                String::from(CS2_HASKELL_BINARY),
+            ],
+            Self::Lambdananas => vec![get_final_path(self.as_str())],
+        }
+    }
+
+    pub fn is_installed(&self) -> bool {
+        self.get_installed_paths()
+            .iter()
+            .any(|path| Path::new(path).exists())
+    }
+
+    pub fn uninstall(&self) -> Result<()> {
+        let _lock = lock::acquire()?;
+
+        if !self.is_installed() {
+            return Err(PackagesError::NotFound(self.clone()).into());
+        }
+
+        println!("Uninstalling {}", self);
+
+        for path in self.get_installed_paths() {
+            remove_path(&path)?;
         }
+        state::clear(self.as_str())
+    }
 
+    /// Reinstalls the binary that was replaced by the last update.
+    /// The current binary becomes the previous one, so rolling back twice undoes it.
+    pub fn rollback(&self) -> Result<()> {
+        let package = self.as_str();
+        let _lock = lock::acquire()?;
+
+        if *self == Self::Cs2Haskell {
+            return Err(PackagesError::RollbackUnsupported(self.clone()).into());
+        }
+        if !Path::new(&get_final_path(package)).exists() {
+            return Err(PackagesError::NotFound(self.clone()).into());
+        }
+
+        let backup_path = state::get_backup_path(package)?;
+        let Some(previous) = state::read_record(package, "previous") else {
+            return Err(PackagesError::NoPreviousVersion(self.clone()).into());
+        };
+        if !backup_path.exists() {
+            return Err(PackagesError::NoPreviousVersion(self.clone()).into());
+        }
+        if sha256::try_digest(&backup_path)? != previous.checksum {
+            return Err(anyhow!(
+                "The backup of {} doesn't match its recorded checksum",
+                package
+            ));
+        }
+
+        println!("Rolling back {} to {}", package, previous.version);
+
+        let temp_path = get_temp_path(package);
+        fs::copy(&backup_path, &temp_path)?;
+
+        self.backup()?;
         self.build()?;
-        _ = warn_path_var("/usr/local/bin");
+        self.record_installed(&previous.version)
+    }
 
-        Ok(())
+    /// Saves the channel so the next updates follow it too
+    fn remember_channel(&self, channel: &Option<Channel>) -> Result<()> {
+        match channel {
+            Some(channel) => state::write_setting(self.as_str(), "channel", &channel.to_string()),
+            None => Ok(()),
+        }
     }
 
-    pub fn update(&self, force: bool) -> Result<()> {
+    pub fn update(&self, options: &UpdateOptions) -> Result<()> {
         let package = self.as_str();
         let path = get_final_path(package);
+        let _lock = lock::acquire()?;
+
+        // Building cs2-haskell needs cargo, which most users don't have.
+        // Prebuilt binaries only exist for tagged releases though.
+        if *self == Self::Cs2Haskell {
+            let follows_releases = matches!(options.channel, None | Some(Channel::Stable));
+
+            if options.prebuilt && !follows_releases {
+                return Err(anyhow!(
+                    "Prebuilt binaries are only available on the stable channel"
+                ));
+            }
+            if options.prebuilt || (follows_releases && find_in_path("cargo").is_none()) {
+                println!("Updating {} from the latest release", package);
+                update_from_release(self.get_release_api(), options.force)?;
+                return self.remember_channel(&options.channel);
+            }
+        }
 
         if !Path::new(&path).exists() {
             return Err(PackagesError::NotFound(self.clone()).into());
@@ -186,24 +448,34 @@ impl Packages {
 
         match *self {
             Self::Cs2Haskell => {
-                if pull_repo(&path, self.as_str())? || force {
+                let channel = options.channel.clone().unwrap_or(Channel::Nightly);
+
+                if pull_repo(
+                    &path,
+                    package,
+                    options.force,
+                    options.stash,
+                    options.discard_changes,
+                    &channel,
+                )? || options.force
+                {
                     self.build()?;
                 } else {
                     println!("Nothing to update");
                 }
+                self.remember_channel(&options.channel)?;
             }
             Self::Lambdananas => {
                 let temp_path = get_temp_path(package);
 
-                download_latest_release(
-                    LAMBDANANAS_RELEASE_API,
-                    LAMBDANANAS_RELEASE_LINK,
-                    &temp_path,
-                )?;
+                let version = self.fetch(&options.source, &temp_path)?;
 
                 if !compare_sha_sums(&temp_path, &path)? {
-                    self.build()?
+                    self.backup()?;
+                    self.build()?;
+                    self.record_installed(&version)?;
                 } else {
+                    _ = fs::remove_file(&temp_path);
                     println!("Nothing to update");
                 }
             }
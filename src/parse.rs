@@ -1,9 +1,12 @@
 use anyhow::{anyhow, Result};
 use std::fmt;
-use std::process::Command;
+use std::fs;
+use std::io::{self, Read};
 use std::str::FromStr;
 
 use crate::ci::Ci;
+use crate::ignore_rules::IgnoreRules;
+use crate::project::Project;
 use crate::shared;
 use regex::Regex;
 
@@ -57,6 +60,66 @@ impl ErrorLevel {
     fn to_color_str(&self) -> &'static str {
         self.to_color().as_str()
     }
+
+    /// Position from the most severe level to the least
+    fn get_rank(&self) -> u8 {
+        match *self {
+            Self::Fatal => 0,
+            Self::Major => 1,
+            Self::Minor => 2,
+            Self::Info => 3,
+        }
+    }
+}
+
+/// What the errors are shown under
+#[derive(Clone, Copy, PartialEq)]
+pub enum GroupBy {
+    File,
+    Rule,
+    Level,
+}
+
+impl FromStr for GroupBy {
+    type Err = anyhow::Error;
+
+    fn from_str(input: &str) -> Result<Self> {
+        match input.to_lowercase().as_str() {
+            "file" => Ok(Self::File),
+            "rule" => Ok(Self::Rule),
+            "level" => Ok(Self::Level),
+            _ => Err(anyhow!("Unknown group, use file, rule or level")),
+        }
+    }
+}
+
+impl GroupBy {
+    fn get_key(&self, error: &LineError) -> String {
+        match *self {
+            Self::File => error.file.clone(),
+            Self::Rule => error.rule.clone(),
+            Self::Level => error.level.to_string(),
+        }
+    }
+}
+
+/// Order of the groups: by file name, rule name and severity, or biggest groups first
+#[derive(Clone, Copy, PartialEq)]
+pub enum SortBy {
+    Name,
+    Count,
+}
+
+impl FromStr for SortBy {
+    type Err = anyhow::Error;
+
+    fn from_str(input: &str) -> Result<Self> {
+        match input.to_lowercase().as_str() {
+            "name" => Ok(Self::Name),
+            "count" => Ok(Self::Count),
+            _ => Err(anyhow!("Unknown order, use name or count")),
+        }
+    }
 }
 
 #[derive(Clone)]
@@ -68,6 +131,10 @@ pub struct LineError {
     pub(crate) rule: String,
     pub(crate) description: String,
     pub(crate) ignore: bool,
+    /// Rule that made the error ignored
+    pub(crate) ignore_reason: Option<String>,
+    /// Cabal or stack component the file belongs to
+    pub(crate) component: Option<String>,
     occurrences: u32,
 }
 
@@ -83,14 +150,6 @@ impl PartialEq for LineError {
     }
 }
 
-fn skip_leading_dot(file: &str) -> &str {
-    let mut chars = file.chars();
-    // for some reason, chars.skip(2) did not work?
-    chars.next();
-    chars.next();
-    chars.as_str()
-}
-
 fn parse_line(line: String) -> Option<LineError> {
     // Forbidden extension special message
     if line.contains("contains forbidden extension") {
@@ -103,6 +162,8 @@ fn parse_line(line: String) -> Option<LineError> {
             rule: String::from("H-E1"),
             description: String::from("Forbidden extension"),
             ignore: false,
+            ignore_reason: None,
+            component: None,
             occurrences: 1,
         });
     }
@@ -119,11 +180,7 @@ fn parse_line(line: String) -> Option<LineError> {
         } else {
             Some(line_nb.to_string().parse().unwrap())
         };
-        let file = if file.starts_with("./") {
-            skip_leading_dot(file)
-        } else {
-            file
-        };
+        let file = shared::skip_leading_dot(file);
         return Some(LineError {
             file: file.to_string(),
             line_nb,
@@ -132,13 +189,15 @@ fn parse_line(line: String) -> Option<LineError> {
             rule: rule.to_string(),
             description: description.to_string(),
             ignore: false,
+            ignore_reason: None,
+            component: None,
             occurrences: 1,
         });
     }
     None
 }
 
-fn summary_errors(errors: &Vec<LineError>) {
+fn summary_errors(errors: &Vec<LineError>, show_ignored: bool) {
     let mut ignored_errors = 0;
     let mut errors_level = [
         (ErrorLevel::Fatal, 0),
@@ -161,11 +220,17 @@ fn summary_errors(errors: &Vec<LineError>) {
     }
 
     if ignored_errors > 0 {
+        let hint = if show_ignored {
+            "use --no-ignore to check them"
+        } else {
+            "use --show-ignored to see why, or --no-ignore to check them"
+        };
         println!(
-            "{}{} ignored errors{} (use --no-ignore to see them)",
+            "{}{} ignored errors{} ({})",
             shared::Colors::BOLD,
             ignored_errors,
-            shared::Colors::RESET
+            shared::Colors::RESET,
+            hint
         );
     }
 
@@ -209,75 +274,134 @@ fn summary_errors(errors: &Vec<LineError>) {
     println!();
 }
 
-fn print_errors(errors: &Vec<LineError>) {
-    let mut prev_file_name = String::new();
+fn print_error(error: &LineError) {
+    print!(
+        "{}{} [{}]:{}",
+        error.level.to_color_str(),
+        error.level,
+        error.rule,
+        shared::Colors::RESET
+    );
+    print!(" {} ", error.description);
+    print!("{}", shared::Colors::GRAY);
+    print!("({}", error.file);
+    if let Some(line_nb) = error.line_nb {
+        print!(":{}", line_nb);
+    }
+    if let Some(col_nb) = error.col_nb {
+        print!(":{}", col_nb);
+    }
+    print!(")");
+    if error.occurrences > 1 {
+        print!(" (x{})", error.occurrences)
+    }
+    println!("{}", shared::Colors::RESET);
+}
+
+/// Lists the ignored errors under the rule that ignored them
+fn print_ignored_errors(errors: &[LineError]) {
+    let mut reasons: Vec<(&str, Vec<&LineError>)> = Vec::new();
 
-    for error in errors {
-        if error.ignore {
-            continue;
-        }
+    for error in errors.iter().filter(|error| error.ignore) {
+        let reason = error.ignore_reason.as_deref().unwrap_or("unknown rule");
 
-        if prev_file_name.is_empty() || prev_file_name != error.file {
-            println!(
-                "{}{}:{}",
-                shared::Colors::BOLD,
-                error.file,
-                shared::Colors::RESET
-            );
+        match reasons.iter_mut().find(|(other, _)| *other == reason) {
+            Some((_, reason_errors)) => reason_errors.push(error),
+            None => reasons.push((reason, vec![error])),
         }
+    }
 
-        print!(
-            "{}{} [{}]:{}",
-            error.level.to_color_str(),
-            error.level,
-            error.rule,
-            shared::Colors::RESET
+    for (reason, reason_errors) in reasons {
+        println!(
+            "{}Ignored by {}{} ({} error(s)):",
+            shared::Colors::BOLD,
+            reason,
+            shared::Colors::RESET,
+            reason_errors.len()
         );
-        print!(" {} ", error.description);
-        print!("{}", shared::Colors::GRAY);
-        print!("({}", error.file);
-        if let Some(line_nb) = error.line_nb {
-            print!(":{}", line_nb);
-        }
-        if let Some(col_nb) = error.col_nb {
-            print!(":{}", col_nb);
+        for error in reason_errors {
+            print!("  ");
+            print_error(error);
         }
-        print!(")");
-        if error.occurrences > 1 {
-            print!(" (x{})", error.occurrences)
+    }
+}
+
+/// Errors that aren't ignored under the key of their group, in the order they are shown
+fn group_errors(
+    errors: &[LineError],
+    group_by: GroupBy,
+    sort: SortBy,
+) -> Vec<(String, Vec<&LineError>)> {
+    let mut groups: Vec<(String, Vec<&LineError>)> = Vec::new();
+
+    for error in errors.iter().filter(|error| !error.ignore) {
+        let key = group_by.get_key(error);
+
+        match groups.iter_mut().find(|(other, _)| *other == key) {
+            Some((_, group)) => group.push(error),
+            None => groups.push((key, vec![error])),
         }
-        println!("{}", shared::Colors::RESET);
-        prev_file_name = error.file.clone();
     }
 
-    summary_errors(errors);
+    // Errors are already sorted by file
+    match group_by {
+        GroupBy::File => {}
+        GroupBy::Rule => groups.sort_by(|(a, _), (b, _)| a.cmp(b)),
+        GroupBy::Level => groups.sort_by_key(|(_, group)| group[0].level.get_rank()),
+    }
+    if sort == SortBy::Count {
+        groups.sort_by_key(|(_, group)| std::cmp::Reverse(group.len()));
+    }
+    groups
 }
 
-fn verify_ignore(errors: &mut Vec<LineError>) -> Result<()> {
-    let command = Command::new("git").args(["clean", "-ndX"]).output()?;
+fn print_errors(errors: &Vec<LineError>, show_ignored: bool, group_by: GroupBy, sort: SortBy) {
+    for (key, group) in group_errors(errors, group_by, sort) {
+        let color = match group_by {
+            GroupBy::Level => group[0].level.to_color_str(),
+            _ => shared::Colors::BOLD.as_str(),
+        };
+
+        print!("{}{}:{}", color, key, shared::Colors::RESET);
+        match group_by {
+            GroupBy::File => {
+                if let Some(component) = &group[0].component {
+                    print!(
+                        " {}({}){}",
+                        shared::Colors::GRAY,
+                        component,
+                        shared::Colors::RESET
+                    );
+                }
+            }
+            _ => print!(
+                " {}({} error(s)){}",
+                shared::Colors::GRAY,
+                group.len(),
+                shared::Colors::RESET
+            ),
+        }
+        println!();
 
-    if !command.status.success() {
-        // We're probably not in a git repo, no need to error out.
-        return Ok(());
+        for error in group {
+            print_error(error);
+        }
     }
 
-    let ignored_files = String::from_utf8(command.stdout)
-        .unwrap()
-        .replace("Would remove ", "");
+    if show_ignored {
+        print_ignored_errors(errors);
+    }
+    summary_errors(errors, show_ignored);
+}
 
-    for ignored_file in ignored_files
-        .split("\n")
-        .map(String::from)
-        .collect::<Vec<_>>()
-    {
-        for error in &mut *errors {
-            if ignored_file.is_empty() {
-                continue;
-            } else if error.file == ignored_file
-                || ignored_file.ends_with('/') && error.file.starts_with(&ignored_file)
-            {
-                error.ignore = true;
-            }
+/// Marks the errors of files matched by .gitignore or .cs2ignore rules
+fn verify_ignore(errors: &mut Vec<LineError>) -> Result<()> {
+    let mut rules = IgnoreRules::new()?;
+
+    for error in errors {
+        if let Some(reason) = rules.get_ignore_reason(&error.file) {
+            error.ignore = true;
+            error.ignore_reason = Some(reason);
         }
     }
     Ok(())
@@ -306,23 +430,50 @@ fn my_dedup(errors: &mut Vec<LineError>) {
 
 /// remove duplicates by checking with PartialEq (dedup)
 fn clean_errors_vector(errors: &mut Vec<LineError>) {
-    errors.sort_by(|a, b| a.line_nb.cmp(&b.line_nb));
-    errors.sort_by(|a, b| a.col_nb.cmp(&b.col_nb));
-    errors.sort_by(|a, b| a.file.to_lowercase().cmp(&b.file.to_lowercase()));
+    errors.sort_by_key(|a| a.line_nb);
+    errors.sort_by_key(|a| a.col_nb);
+    errors.sort_by_key(|a| a.file.to_lowercase());
 
     my_dedup(errors);
 }
 
+/// Lines of lambdananas output saved in path, or given on stdin with -
+pub fn read_input(path: &str) -> Result<Vec<String>> {
+    let content = if path == "-" {
+        let mut content = Vec::new();
+        io::stdin().lock().read_to_end(&mut content)?;
+        content
+    } else {
+        fs::read(path).map_err(|e| anyhow!("Couldn't read {}: {}", path, e))?
+    };
+
+    Ok(String::from_utf8_lossy(&content)
+        .lines()
+        .map(String::from)
+        .collect())
+}
+
 /// Returns true if needs to be exited, returns false if it doesn't
-pub fn parse_output(lines: Vec<String>, dont_ignore: bool, ci: Option<Ci>) -> Result<bool> {
+pub fn parse_output(
+    lines: Vec<String>,
+    dont_ignore: bool,
+    ci: Option<Ci>,
+    project: Option<&Project>,
+    show_ignored: bool,
+    group_by: GroupBy,
+    sort: SortBy,
+) -> Result<bool> {
     let mut errors: Vec<LineError> = Vec::new();
 
     for line in lines {
-        let line_error = match parse_line(line) {
+        let mut line_error = match parse_line(line) {
             Some(error) => error,
             None => continue,
         };
 
+        if let Some(project) = project {
+            line_error.component = project.find_component(&line_error.file);
+        }
         errors.push(line_error);
     }
 
@@ -330,7 +481,7 @@ pub fn parse_output(lines: Vec<String>, dont_ignore: bool, ci: Option<Ci>) -> Re
         verify_ignore(&mut errors)?;
     }
     clean_errors_vector(&mut errors);
-    print_errors(&errors);
+    print_errors(&errors, show_ignored, group_by, sort);
 
     if let Some(ci) = ci {
         ci.print_errors(&errors);
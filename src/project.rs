@@ -0,0 +1,638 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Component as PathComponent, Path, PathBuf};
+
+use anyhow::{anyhow, Result};
+use serde_yaml::Value;
+
+use crate::shared;
+
+/// Directories where cabal and stack put their build artifacts, never checked
+pub const ARTIFACT_DIRS: [&str; 2] = ["dist-newstyle", ".stack-work"];
+
+/// Part of a package built on its own, such as lib:foo or exe:foo
+pub struct Component {
+    pub name: String,
+    /// hs-source-dirs, relative to the project root like ./foo/src
+    pub source_dirs: Vec<String>,
+}
+
+/// Cabal or stack project, possibly made of several packages
+pub struct Project {
+    pub components: Vec<Component>,
+}
+
+/// A stanza of a .cabal file, common stanzas having no kind
+struct Stanza {
+    kind: Option<&'static str>,
+    name: Option<String>,
+    source_dirs: Vec<String>,
+    imports: Vec<String>,
+}
+
+/// Joins dir to package_dir, written like ./package_dir/dir or . for the project root
+fn join_dir(package_dir: &Path, dir: &str) -> String {
+    let path = package_dir
+        .join(dir.trim_matches('"'))
+        .components()
+        .filter(|component| *component != PathComponent::CurDir)
+        .collect::<PathBuf>();
+
+    if path.as_os_str().is_empty() {
+        String::from(".")
+    } else {
+        format!("./{}", path.display())
+    }
+}
+
+fn get_stanza_kind(keyword: &str) -> Option<Option<&'static str>> {
+    match keyword.to_ascii_lowercase().as_str() {
+        "library" => Some(Some("lib")),
+        "foreign-library" => Some(Some("flib")),
+        "executable" => Some(Some("exe")),
+        "test-suite" => Some(Some("test")),
+        "benchmark" => Some(Some("bench")),
+        "common" => Some(None),
+        _ => None,
+    }
+}
+
+/// Source directories of a common stanza and the ones it imports
+fn get_common_dirs<'a>(
+    commons: &'a HashMap<String, &Stanza>,
+    name: &str,
+    seen: &mut Vec<String>,
+) -> Vec<&'a String> {
+    let Some(common) = commons.get(name) else {
+        return Vec::new();
+    };
+
+    if seen.iter().any(|seen| seen == name) {
+        return Vec::new();
+    }
+    seen.push(name.to_string());
+
+    let mut dirs = common.source_dirs.iter().collect::<Vec<_>>();
+    for import in &common.imports {
+        dirs.append(&mut get_common_dirs(commons, import, seen));
+    }
+    dirs
+}
+
+/// Components of the package described by the content of a .cabal file,
+/// package_name being used when it doesn't have a name field
+fn parse_cabal(content: &str, package_name: &str, package_dir: &Path) -> Vec<Component> {
+    let lines = content
+        .lines()
+        .filter(|line| !line.trim_start().starts_with("--"))
+        .collect::<Vec<_>>();
+    let mut package_name = package_name.to_string();
+    let mut stanzas: Vec<Stanza> = Vec::new();
+    let mut in_stanza = false;
+
+    let mut i = 0;
+    while i < lines.len() {
+        let line = lines[i];
+        let trimmed = line.trim_start();
+        let indent = line.len() - trimmed.len();
+        i += 1;
+
+        if trimmed.is_empty() {
+            continue;
+        }
+
+        // Stanzas and top-level fields start at the first column
+        if indent == 0 {
+            let mut words = trimmed.split_whitespace();
+
+            in_stanza = false;
+            if let Some(kind) = words.next().and_then(get_stanza_kind) {
+                stanzas.push(Stanza {
+                    kind,
+                    name: words.next().map(String::from),
+                    source_dirs: Vec::new(),
+                    imports: Vec::new(),
+                });
+                in_stanza = true;
+            } else if let Some((field, value)) = trimmed.split_once(':') {
+                if field.trim().eq_ignore_ascii_case("name") {
+                    package_name = value.trim().to_string();
+                }
+            }
+            continue;
+        }
+
+        let (Some(stanza), true) = (stanzas.last_mut(), in_stanza) else {
+            continue;
+        };
+        let Some((field, value)) = trimmed.split_once(':') else {
+            continue;
+        };
+        let field = field.trim().to_ascii_lowercase();
+        if field != "hs-source-dirs" && field != "import" {
+            continue;
+        }
+
+        // Values can continue on the next lines, indented further
+        let mut value = value.to_string();
+        while i < lines.len() {
+            let next = lines[i].trim_start();
+
+            if next.is_empty() || lines[i].len() - next.len() <= indent {
+                break;
+            }
+            value.push(' ');
+            value.push_str(next);
+            i += 1;
+        }
+
+        let values = value
+            .split([',', ' ', '\t'])
+            .filter(|value| !value.is_empty())
+            .map(String::from);
+        if field == "import" {
+            stanza.imports.extend(values);
+        } else {
+            stanza.source_dirs.extend(values);
+        }
+    }
+
+    let commons = stanzas
+        .iter()
+        .filter(|stanza| stanza.kind.is_none())
+        .filter_map(|stanza| Some((stanza.name.clone()?, stanza)))
+        .collect::<HashMap<_, _>>();
+    let mut components = Vec::new();
+
+    for stanza in &stanzas {
+        let Some(kind) = stanza.kind else {
+            continue;
+        };
+
+        let mut dirs = stanza.source_dirs.iter().collect::<Vec<_>>();
+        for import in &stanza.imports {
+            dirs.append(&mut get_common_dirs(&commons, import, &mut Vec::new()));
+        }
+
+        let mut source_dirs = dirs
+            .iter()
+            .map(|dir| join_dir(package_dir, dir))
+            .collect::<Vec<_>>();
+        // Cabal looks in the package directory when hs-source-dirs isn't set
+        if source_dirs.is_empty() {
+            source_dirs.push(join_dir(package_dir, "."));
+        }
+
+        components.push(Component {
+            name: format!("{}:{}", kind, stanza.name.as_ref().unwrap_or(&package_name)),
+            source_dirs,
+        });
+    }
+    components
+}
+
+/// Values of source-dirs, which can be a single directory or a list
+fn get_hpack_dirs(section: &Value) -> Vec<String> {
+    match section.get("source-dirs") {
+        Some(Value::String(dir)) => vec![dir.clone()],
+        Some(Value::Sequence(dirs)) => dirs
+            .iter()
+            .filter_map(|dir| dir.as_str().map(String::from))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Components of the package described by the content of an hpack package.yaml
+fn parse_hpack(content: &str, package_dir: &Path) -> Result<Vec<Component>> {
+    let package: Value = serde_yaml::from_str(content)?;
+    let package_name = package
+        .get("name")
+        .and_then(|name| name.as_str())
+        .map(String::from)
+        .or_else(|| {
+            package_dir
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+        })
+        .unwrap_or_default();
+    // Top-level fields are shared by every component
+    let common_dirs = get_hpack_dirs(&package);
+
+    let mut sections = Vec::new();
+    if let Some(library) = package.get("library") {
+        sections.push((format!("lib:{}", package_name), library));
+    }
+    if let Some(executable) = package.get("executable") {
+        sections.push((format!("exe:{}", package_name), executable));
+    }
+    for (field, kind) in [
+        ("internal-libraries", "lib"),
+        ("executables", "exe"),
+        ("tests", "test"),
+        ("benchmarks", "bench"),
+    ] {
+        if let Some(Value::Mapping(mapping)) = package.get(field) {
+            for (name, section) in mapping {
+                if let Some(name) = name.as_str() {
+                    sections.push((format!("{}:{}", kind, name), section));
+                }
+            }
+        }
+    }
+
+    Ok(sections
+        .into_iter()
+        .map(|(name, section)| {
+            let mut dirs = common_dirs.clone();
+            dirs.append(&mut get_hpack_dirs(section));
+            if dirs.is_empty() {
+                dirs.push(String::from("."));
+            }
+
+            Component {
+                name,
+                source_dirs: dirs.iter().map(|dir| join_dir(package_dir, dir)).collect(),
+            }
+        })
+        .collect())
+}
+
+/// First file of package_dir matching pattern
+fn find_file(package_dir: &Path, pattern: &str) -> Option<PathBuf> {
+    let pattern = package_dir.join(pattern);
+
+    glob::glob(&pattern.to_string_lossy())
+        .ok()?
+        .flatten()
+        .next()
+}
+
+/// Components of the package in package_dir, from package.yaml or its .cabal file
+fn parse_package(package_dir: &Path) -> Result<Vec<Component>> {
+    // The .cabal file is generated from package.yaml when there is one
+    let hpack = package_dir.join("package.yaml");
+    if hpack.exists() {
+        return parse_hpack(&fs::read_to_string(&hpack)?, package_dir);
+    }
+
+    let Some(cabal) = find_file(package_dir, "*.cabal") else {
+        return Ok(Vec::new());
+    };
+    let package_name = cabal
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default();
+    Ok(parse_cabal(
+        &fs::read_to_string(&cabal)?,
+        &package_name,
+        package_dir,
+    ))
+}
+
+/// Package directories matching the entries of packages, which may be globs
+fn find_package_dirs(packages: &[String]) -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+
+    for package in packages {
+        // Remote packages aren't part of the project
+        if package.contains("://") || package.ends_with(".tar.gz") {
+            continue;
+        }
+
+        let Ok(paths) = glob::glob(package.trim_end_matches('/')) else {
+            continue;
+        };
+        for path in paths.flatten() {
+            let dir = if path.is_dir() {
+                path
+            } else {
+                match path.parent() {
+                    Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+                    _ => PathBuf::from("."),
+                }
+            };
+
+            if !dirs.contains(&dir) {
+                dirs.push(dir);
+            }
+        }
+    }
+    dirs
+}
+
+/// Entries of the packages field of cabal.project
+fn parse_cabal_project(path: &Path) -> Result<Vec<String>> {
+    let content = fs::read_to_string(path)?;
+    let mut packages = Vec::new();
+    let mut in_packages = false;
+
+    for line in content.lines() {
+        let trimmed = line.trim();
+
+        if trimmed.is_empty() || trimmed.starts_with("--") {
+            continue;
+        }
+
+        if !line.starts_with([' ', '\t']) {
+            in_packages = false;
+            match trimmed.split_once(':') {
+                Some((field, value)) if field.trim().eq_ignore_ascii_case("packages") => {
+                    in_packages = true;
+                    packages.extend(value.split_whitespace().map(String::from));
+                }
+                _ => {}
+            }
+        } else if in_packages {
+            packages.extend(trimmed.split_whitespace().map(String::from));
+        }
+    }
+
+    // Default of cabal when packages isn't set
+    if packages.is_empty() {
+        packages.push(String::from("./*.cabal"));
+    }
+    Ok(packages)
+}
+
+/// Entries of the packages field of stack.yaml
+fn parse_stack_yaml(path: &Path) -> Result<Vec<String>> {
+    let config: Value = serde_yaml::from_str(&fs::read_to_string(path)?)?;
+
+    match config.get("packages") {
+        Some(Value::Sequence(packages)) => Ok(packages
+            .iter()
+            .filter_map(|package| package.as_str().map(String::from))
+            .collect()),
+        _ => Ok(vec![String::from(".")]),
+    }
+}
+
+/// Looks for a cabal or stack project in the current directory
+pub fn detect() -> Result<Option<Project>> {
+    let packages = if Path::new("cabal.project").exists() {
+        parse_cabal_project(Path::new("cabal.project"))
+            .map_err(|e| anyhow!("Couldn't read cabal.project: {}", e))?
+    } else if Path::new("stack.yaml").exists() {
+        parse_stack_yaml(Path::new("stack.yaml"))
+            .map_err(|e| anyhow!("Couldn't read stack.yaml: {}", e))?
+    } else if Path::new("package.yaml").exists() || find_file(Path::new("."), "*.cabal").is_some() {
+        vec![String::from(".")]
+    } else {
+        return Ok(None);
+    };
+
+    let mut components = Vec::new();
+    for package_dir in find_package_dirs(&packages) {
+        components.append(&mut parse_package(&package_dir).map_err(|e| {
+            anyhow!(
+                "Couldn't read the package in {}: {}",
+                package_dir.display(),
+                e
+            )
+        })?);
+    }
+
+    if components.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(Project { components }))
+}
+
+impl Project {
+    /// Source directories of every component, each one only once
+    pub fn get_source_dirs(&self) -> Vec<String> {
+        let mut dirs: Vec<String> = Vec::new();
+
+        for component in &self.components {
+            for dir in &component.source_dirs {
+                if !dirs.contains(dir) {
+                    dirs.push(dir.clone());
+                }
+            }
+        }
+        dirs
+    }
+
+    /// Components whose source directories contain file, the most specific ones only
+    pub fn find_component(&self, file: &str) -> Option<String> {
+        let file = shared::skip_leading_dot(file);
+        let mut best_length = None;
+        let mut names: Vec<&str> = Vec::new();
+
+        for component in &self.components {
+            for dir in &component.source_dirs {
+                let dir = shared::skip_leading_dot(dir);
+                // The package root contains every file, but less specifically than any other dir
+                let (contains, length) = if dir == "." {
+                    (true, 0)
+                } else {
+                    let contains = file
+                        .strip_prefix(dir)
+                        .is_some_and(|rest| rest.starts_with('/'));
+                    (contains, dir.len())
+                };
+
+                if !contains || best_length.is_some_and(|best| length < best) {
+                    continue;
+                }
+                if best_length != Some(length) {
+                    best_length = Some(length);
+                    names.clear();
+                }
+                if !names.contains(&component.name.as_str()) {
+                    names.push(&component.name);
+                }
+            }
+        }
+
+        if names.is_empty() {
+            None
+        } else {
+            Some(names.join(", "))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_dirs<'a>(components: &'a [Component], name: &str) -> &'a [String] {
+        &components
+            .iter()
+            .find(|component| component.name == name)
+            .unwrap_or_else(|| panic!("no component {}", name))
+            .source_dirs
+    }
+
+    #[test]
+    fn cabal_common_stanzas_are_imported() {
+        let content = "\
+name: foo
+
+common warnings
+  ghc-options: -Wall
+
+common shared
+  import: warnings
+  hs-source-dirs: shared
+
+library
+  import: shared
+  hs-source-dirs: src
+";
+        let components = parse_cabal(content, "foo", Path::new("."));
+
+        assert_eq!(components.len(), 1);
+        assert_eq!(get_dirs(&components, "lib:foo"), ["./src", "./shared"]);
+    }
+
+    #[test]
+    fn cabal_source_dirs_can_span_several_lines() {
+        let content = "\
+name: foo
+
+executable foo-exe
+  main-is: Main.hs
+  hs-source-dirs:
+      app,
+      src
+  -- a comment
+  build-depends: base
+";
+        let components = parse_cabal(content, "foo", Path::new("pkg"));
+
+        assert_eq!(
+            get_dirs(&components, "exe:foo-exe"),
+            ["./pkg/app", "./pkg/src"]
+        );
+    }
+
+    #[test]
+    fn cabal_conditional_source_dirs_are_included() {
+        let content = "\
+name: foo
+
+library
+  hs-source-dirs: src
+  if flag(dev)
+    hs-source-dirs: dev
+  else
+    hs-source-dirs: \"release\"
+";
+        let components = parse_cabal(content, "foo", Path::new("."));
+
+        assert_eq!(
+            get_dirs(&components, "lib:foo"),
+            ["./src", "./dev", "./release"]
+        );
+    }
+
+    #[test]
+    fn cabal_defaults_to_the_package_dir() {
+        let content = "\
+test-suite spec
+  type: exitcode-stdio-1.0
+  main-is: Spec.hs
+";
+        assert_eq!(
+            get_dirs(&parse_cabal(content, "foo", Path::new(".")), "test:spec"),
+            ["."]
+        );
+        assert_eq!(
+            get_dirs(&parse_cabal(content, "foo", Path::new("pkg")), "test:spec"),
+            ["./pkg"]
+        );
+    }
+
+    #[test]
+    fn cabal_package_name_is_used_for_unnamed_stanzas() {
+        let content = "\
+name: real-name
+library
+  hs-source-dirs: src
+";
+        let components = parse_cabal(content, "file-stem", Path::new("."));
+
+        assert_eq!(components[0].name, "lib:real-name");
+    }
+
+    #[test]
+    fn hpack_source_dirs_are_merged_with_top_level_ones() {
+        let content = "\
+name: foo
+source-dirs: common
+library:
+  source-dirs: src
+executables:
+  foo-exe:
+    main: Main.hs
+    source-dirs: [app, cli]
+tests:
+  spec:
+    main: Spec.hs
+";
+        let components = parse_hpack(content, Path::new(".")).unwrap();
+
+        assert_eq!(get_dirs(&components, "lib:foo"), ["./common", "./src"]);
+        assert_eq!(
+            get_dirs(&components, "exe:foo-exe"),
+            ["./common", "./app", "./cli"]
+        );
+        assert_eq!(get_dirs(&components, "test:spec"), ["./common"]);
+    }
+
+    #[test]
+    fn hpack_defaults_to_the_package_dir() {
+        let components = parse_hpack("name: foo\nlibrary: {}\n", Path::new("pkg")).unwrap();
+
+        assert_eq!(get_dirs(&components, "lib:foo"), ["./pkg"]);
+    }
+
+    fn component(name: &str, source_dirs: &[&str]) -> Component {
+        Component {
+            name: String::from(name),
+            source_dirs: source_dirs.iter().map(|dir| dir.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn find_component_prefers_the_most_specific_dir() {
+        let project = Project {
+            components: vec![
+                component("lib:foo", &["./src"]),
+                component("exe:foo-exe", &["./src", "./app"]),
+                component("lib:internal", &["./src/Internal"]),
+                component("test:spec", &["."]),
+            ],
+        };
+
+        assert_eq!(
+            project.find_component("./src/Lib.hs").as_deref(),
+            Some("lib:foo, exe:foo-exe")
+        );
+        assert_eq!(
+            project.find_component("src/Internal/Types.hs").as_deref(),
+            Some("lib:internal")
+        );
+        assert_eq!(
+            project.find_component("./app/Main.hs").as_deref(),
+            Some("exe:foo-exe")
+        );
+        assert_eq!(
+            project.find_component("./Setup.hs").as_deref(),
+            Some("test:spec")
+        );
+    }
+
+    #[test]
+    fn find_component_matches_whole_dir_names() {
+        let project = Project {
+            components: vec![component("lib:foo", &["./src"])],
+        };
+
+        assert_eq!(project.find_component("./src-gen/Lib.hs"), None);
+        assert_eq!(project.find_component("./srcLib.hs"), None);
+    }
+}
@@ -1,9 +1,15 @@
+use std::env;
 use std::fmt;
-use std::path::Path;
+use std::io::{self, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
 use std::process::Command;
 
 use anyhow::{anyhow, Result};
 
+/// Overrides the lambdananas executable, like --lambdananas
+pub const LAMBDANANAS_ENV_VAR: &str = "CS2_LAMBDANANAS";
+
 #[allow(clippy::upper_case_acronyms)]
 pub enum Colors {
     GRAY,
@@ -33,10 +39,43 @@ impl fmt::Display for Colors {
     }
 }
 
-pub fn split_output(output: Vec<u8>) -> Result<Vec<String>> {
-    let output_str = String::from_utf8(output)?;
+/// lambdananas executable, from --lambdananas, CS2_LAMBDANANAS or PATH
+pub fn get_lambdananas(lambdananas: Option<&str>) -> String {
+    if let Some(lambdananas) = lambdananas {
+        return lambdananas.to_string();
+    }
+
+    match env::var(LAMBDANANAS_ENV_VAR) {
+        Ok(lambdananas) if !lambdananas.is_empty() => lambdananas,
+        _ => String::from("lambdananas"),
+    }
+}
+
+/// Lines of the output of lambdananas, which may contain paths that aren't valid UTF-8
+pub fn split_output(output: Vec<u8>) -> Vec<String> {
+    let output_str = String::from_utf8_lossy(&output);
+
+    output_str.split("\n").map(String::from).collect::<Vec<_>>()
+}
+
+pub fn skip_leading_dot(file: &str) -> &str {
+    file.strip_prefix("./").unwrap_or(file)
+}
+
+/// File an output line of lambdananas is about, such as
+/// `./src/Main.hs:12: MAJOR:H-F3 # ...` or `./src/Main.hs contains forbidden extension ...`
+pub fn get_output_file(line: &str) -> Option<&str> {
+    // Paths can contain spaces, but not colons since lambdananas separates fields with them
+    let file = match line.split_once(" contains forbidden extension") {
+        Some((file, _)) => file,
+        None => line.split_once(':')?.0,
+    };
 
-    Ok(output_str.split("\n").map(String::from).collect::<Vec<_>>())
+    if file.ends_with(".hs") {
+        Some(skip_leading_dot(file))
+    } else {
+        None
+    }
 }
 
 /// similar to fs::create_dir_all except with sudo privileges
@@ -50,3 +89,76 @@ pub fn create_directory(path: &str) -> Result<()> {
         Err(_) => Err(anyhow!("Couldn't create folder")),
     }
 }
+
+/// Asks a yes/no question, anything other than y/yes is a no
+pub fn confirm(question: &str) -> Result<bool> {
+    print!("{} [y/N] ", question);
+    io::stdout().flush()?;
+
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+
+    Ok(matches!(
+        answer.trim().to_ascii_lowercase().as_str(),
+        "y" | "yes"
+    ))
+}
+
+pub fn is_executable(path: &Path) -> bool {
+    match path.metadata() {
+        Ok(metadata) => metadata.is_file() && metadata.permissions().mode() & 0o111 != 0,
+        Err(_) => false,
+    }
+}
+
+/// Every executable named name in PATH, in the order they would be resolved
+pub fn find_all_in_path(name: &str) -> Vec<PathBuf> {
+    let Some(path_var) = env::var_os("PATH") else {
+        return Vec::new();
+    };
+
+    env::split_paths(&path_var)
+        .map(|directory| directory.join(name))
+        .filter(|path| is_executable(path))
+        .collect()
+}
+
+/// Executable that would be run for name, similar to `which`
+pub fn find_in_path(name: &str) -> Option<PathBuf> {
+    find_all_in_path(name).into_iter().next()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn output_file_is_found_in_every_kind_of_line() {
+        assert_eq!(
+            get_output_file("./src/Main.hs:12: MAJOR:H-F3 # too long"),
+            Some("src/Main.hs")
+        );
+        assert_eq!(
+            get_output_file("./src/Main.hs contains forbidden extension TemplateHaskell"),
+            Some("src/Main.hs")
+        );
+        assert_eq!(
+            get_output_file("src/Main.hs: parse error"),
+            Some("src/Main.hs")
+        );
+        assert_eq!(get_output_file("lambdananas: something failed"), None);
+        assert_eq!(get_output_file(""), None);
+    }
+
+    #[test]
+    fn output_file_can_contain_spaces() {
+        assert_eq!(
+            get_output_file("./my dir/A.hs:1: MAJOR:H-G1 # bad header"),
+            Some("my dir/A.hs")
+        );
+        assert_eq!(
+            get_output_file("./my dir/A.hs contains forbidden extension CPP"),
+            Some("my dir/A.hs")
+        );
+    }
+}
@@ -0,0 +1,105 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Result};
+
+/// Version and checksum of an installed binary
+pub struct Record {
+    pub version: String,
+    pub checksum: String,
+}
+
+impl Record {
+    /// Builds a record for the binary at path, computing its checksum
+    pub fn new(version: &str, path: &str) -> Result<Self> {
+        Ok(Self {
+            version: version.to_string(),
+            checksum: sha256::try_digest(Path::new(path))?,
+        })
+    }
+}
+
+/// $XDG_STATE_HOME/cs2-haskell, or ~/.local/state/cs2-haskell
+pub fn get_state_dir() -> Result<PathBuf> {
+    if let Ok(dir) = env::var("XDG_STATE_HOME") {
+        if !dir.is_empty() {
+            return Ok(Path::new(&dir).join("cs2-haskell"));
+        }
+    }
+
+    match env::var("HOME") {
+        Ok(home) => Ok(Path::new(&home)
+            .join(".local")
+            .join("state")
+            .join("cs2-haskell")),
+        Err(_) => Err(anyhow!(
+            "Couldn't find the state directory, HOME is not set"
+        )),
+    }
+}
+
+fn get_package_dir(package: &str) -> Result<PathBuf> {
+    Ok(get_state_dir()?.join(package))
+}
+
+/// Copy of the binary that was installed before the last update
+pub fn get_backup_path(package: &str) -> Result<PathBuf> {
+    Ok(get_package_dir(package)?.join("previous-binary"))
+}
+
+/// Reads the record `name` (installed or previous) of package
+pub fn read_record(package: &str, name: &str) -> Option<Record> {
+    let content = fs::read_to_string(get_package_dir(package).ok()?.join(name)).ok()?;
+    let mut version = None;
+    let mut checksum = None;
+
+    for line in content.lines() {
+        match line.split_once('=') {
+            Some(("version", value)) => version = Some(value.to_string()),
+            Some(("checksum", value)) => checksum = Some(value.to_string()),
+            _ => {}
+        }
+    }
+
+    Some(Record {
+        version: version?,
+        checksum: checksum?,
+    })
+}
+
+pub fn write_record(package: &str, name: &str, record: &Record) -> Result<()> {
+    let package_dir = get_package_dir(package)?;
+
+    fs::create_dir_all(&package_dir)?;
+    fs::write(
+        package_dir.join(name),
+        format!("version={}\nchecksum={}\n", record.version, record.checksum),
+    )?;
+    Ok(())
+}
+
+/// Forgets everything recorded about package
+pub fn clear(package: &str) -> Result<()> {
+    let package_dir = get_package_dir(package)?;
+
+    if package_dir.exists() {
+        fs::remove_dir_all(package_dir)?;
+    }
+    Ok(())
+}
+
+/// Reads a single value remembered for package, such as its update channel
+pub fn read_setting(package: &str, name: &str) -> Option<String> {
+    let content = fs::read_to_string(get_package_dir(package).ok()?.join(name)).ok()?;
+
+    Some(content.trim().to_string())
+}
+
+pub fn write_setting(package: &str, name: &str, value: &str) -> Result<()> {
+    let package_dir = get_package_dir(package)?;
+
+    fs::create_dir_all(&package_dir)?;
+    fs::write(package_dir.join(name), format!("{}\n", value))?;
+    Ok(())
+}
@@ -0,0 +1,98 @@
+use std::env;
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::commands::update::is_newer;
+use crate::github;
+use crate::package::Packages;
+use crate::shared::Colors;
+use crate::state;
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+/// The check runs while lambdananas does, these keep it from delaying the run when offline
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(3);
+const WAIT_TIMEOUT: Duration = Duration::from_millis(500);
+const DISABLE_ENV_VAR: &str = "CS2_HASKELL_NO_UPDATE_CHECK";
+const PACKAGES: [Packages; 2] = [Packages::Cs2Haskell, Packages::Lambdananas];
+
+fn is_disabled(no_update_check: bool) -> bool {
+    no_update_check || env::var(DISABLE_ENV_VAR).is_ok_and(|value| !value.is_empty())
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+fn is_due() -> bool {
+    match state::read_setting(Packages::Cs2Haskell.as_str(), "last-update-check")
+        .and_then(|last| last.parse::<u64>().ok())
+    {
+        Some(last) => now().saturating_sub(last) >= CHECK_INTERVAL.as_secs(),
+        None => true,
+    }
+}
+
+/// Fetches the latest releases in the background if the last check is more than a day old.
+/// Returns a receiver notified once the results are saved, None if nothing was started.
+pub fn start(no_update_check: bool) -> Option<Receiver<()>> {
+    if is_disabled(no_update_check) || !is_due() {
+        return None;
+    }
+
+    // Saved even if the check fails, so being offline doesn't retry on every run
+    _ = state::write_setting(
+        Packages::Cs2Haskell.as_str(),
+        "last-update-check",
+        &now().to_string(),
+    );
+
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        for package in PACKAGES {
+            if let Ok(release) =
+                github::latest_release_timeout(package.get_release_api(), REQUEST_TIMEOUT)
+            {
+                _ = state::write_setting(package.as_str(), "latest", &release.tag_name);
+            }
+        }
+        _ = sender.send(());
+    });
+    Some(receiver)
+}
+
+/// Prints a one-line hint if a package is older than its latest known release
+pub fn print_hint(pending: Option<Receiver<()>>, no_update_check: bool) {
+    if is_disabled(no_update_check) {
+        return;
+    }
+    if let Some(receiver) = pending {
+        _ = receiver.recv_timeout(WAIT_TIMEOUT);
+    }
+
+    let outdated = PACKAGES
+        .iter()
+        .filter_map(|package| {
+            let latest = state::read_setting(package.as_str(), "latest")?;
+            let installed = package.get_installed_version()?;
+
+            if is_newer(&latest, &installed) {
+                Some(format!("{} {} -> {}", package, installed, latest))
+            } else {
+                None
+            }
+        })
+        .collect::<Vec<_>>();
+
+    if !outdated.is_empty() {
+        println!(
+            "{}Update available: {} (run cs2-haskell update, or use --no-update-check to hide this){}",
+            Colors::GRAY,
+            outdated.join(", "),
+            Colors::RESET
+        );
+    }
+}